@@ -0,0 +1,274 @@
+// Copyright © 2016, Peter Atashian
+
+#[cfg(feature = "gif")]
+use gif::{self, DisposalMethod, SetParameter};
+use error::{Error, Result};
+use load::{decode};
+use pixel::{Pixel};
+use png::{BlendOp, Decoder, DisposeOp, Transformations};
+use std::fs::{File};
+use std::io::{Read};
+use std::time::{Duration};
+use Image;
+
+/// One frame of an animation, already composited with the frames before it.
+pub struct Frame {
+    pub image: Image,
+    /// How long the frame stays on screen.
+    pub delay: Duration,
+}
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// How many times to play through the frames, with zero meaning forever.
+    pub loops: u32,
+}
+impl Animation {
+    fn still(image: Image) -> Animation {
+        Animation {
+            frames: vec![Frame { image, delay: Duration::from_secs(0) }],
+            loops: 1,
+        }
+    }
+}
+/// Loads every frame of an animated GIF or PNG, compositing them over `background`. Any other
+/// image is loaded as a single frame.
+pub fn load_animation(s: &str, background: Pixel) -> Result<Animation> {
+    let mut data = Vec::new();
+    File::open(s)?.read_to_end(&mut data)?;
+    decode_animation(&data, s, background)
+}
+/// Like `load_animation` but for data which has already been read into memory, with `s` being
+/// where it came from.
+pub fn decode_animation(data: &[u8], s: &str, background: Pixel) -> Result<Animation> {
+    let anim = if data.starts_with(b"GIF8") {
+        load_gif(data, background)?
+    } else if let Some(anim) = load_apng(data, background)? {
+        anim
+    } else {
+        return decode(data, s, background).map(Animation::still)
+    };
+    // Everything after this can count on there being a first frame
+    if anim.frames.is_empty() {
+        return Err(Error::Decode(format!("{} has no frames", s)))
+    }
+    Ok(anim)
+}
+// Frames are built up on an 8-bit RGBA canvas the size of the whole animation
+struct Canvas {
+    pixels: Vec<(u8, u8, u8, u8)>,
+    width: u32,
+    height: u32,
+}
+impl Canvas {
+    fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            pixels: vec![(0, 0, 0, 0); (width * height) as usize],
+            width,
+            height,
+        }
+    }
+    // Draws a `w` by `h` RGBA frame with its top left corner at `x`, `y`. When blending, the
+    // frame is composited over what is already there instead of replacing it.
+    fn draw(&mut self, buf: &[u8], (x, y, w, h): (u32, u32, u32, u32), blend: bool) {
+        for (i, p) in buf.chunks(4).enumerate().take((w * h) as usize) {
+            let (xx, yy) = (x + i as u32 % w, y + i as u32 / w);
+            if xx >= self.width || yy >= self.height {
+                continue
+            }
+            let dst = &mut self.pixels[(yy * self.width + xx) as usize];
+            *dst = if blend && p[3] != 255 {
+                over((p[0], p[1], p[2], p[3]), *dst)
+            } else {
+                (p[0], p[1], p[2], p[3])
+            };
+        }
+    }
+    fn clear(&mut self, (x, y, w, h): (u32, u32, u32, u32)) {
+        for yy in y..(y + h).min(self.height) {
+            for xx in x..(x + w).min(self.width) {
+                self.pixels[(yy * self.width + xx) as usize] = (0, 0, 0, 0);
+            }
+        }
+    }
+    fn frame(&self, background: Pixel, delay: Duration) -> Frame {
+        Frame {
+            image: Image::from_srgba(&self.pixels, self.width, self.height, background),
+            delay,
+        }
+    }
+}
+// The usual non-premultiplied over operator, done in the encoded values like browsers do
+fn over(src: (u8, u8, u8, u8), dst: (u8, u8, u8, u8)) -> (u8, u8, u8, u8) {
+    let (sa, da) = (src.3 as u32, dst.3 as u32 * (255 - src.3 as u32) / 255);
+    let a = sa + da;
+    if a == 0 {
+        return (0, 0, 0, 0)
+    }
+    let c = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da) / a) as u8;
+    (c(src.0, dst.0), c(src.1, dst.1), c(src.2, dst.2), a as u8)
+}
+// Browsers show GIF frames with tiny delays for a tenth of a second, and so many animations rely
+// on that.
+#[cfg(feature = "gif")]
+fn gif_delay(ms: u64) -> Duration {
+    Duration::from_millis(if ms < 20 { 100 } else { ms })
+}
+#[cfg(feature = "gif")]
+fn load_gif(data: &[u8], background: Pixel) -> Result<Animation> {
+    let err = |e: gif::DecodingError| Error::Decode(e.to_string());
+    let mut decoder = gif::Decoder::new(data);
+    decoder.set(gif::ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(err)?;
+    let mut canvas = Canvas::new(reader.width() as u32, reader.height() as u32);
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.read_next_frame().map_err(err)? {
+        let rect = (frame.left as u32, frame.top as u32, frame.width as u32, frame.height as u32);
+        let previous = canvas.pixels.clone();
+        canvas.draw(&frame.buffer, rect, true);
+        frames.push(canvas.frame(background, gif_delay(frame.delay as u64 * 10)));
+        match frame.dispose {
+            DisposalMethod::Any | DisposalMethod::Keep => (),
+            DisposalMethod::Background => canvas.clear(rect),
+            DisposalMethod::Previous => canvas.pixels = previous,
+        }
+    }
+    Ok(Animation { frames, loops: gif_loops(data) })
+}
+// The number of loops is hidden away in an application extension which the decoder skips over.
+// Browsers play a GIF without one just once, and otherwise repeat it as many more times as it
+// asks, with zero meaning forever.
+#[cfg(feature = "gif")]
+fn gif_loops(data: &[u8]) -> u32 {
+    // The size of the color table which follows a block with these flags
+    let table = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 7) + 1) } else { 0 };
+    // Where the data sub-blocks starting at `i` end
+    let blocks = |mut i: usize| {
+        while let Some(&len) = data.get(i) {
+            i += 1 + len as usize;
+            if len == 0 {
+                break
+            }
+        }
+        i
+    };
+    let mut i = match data.get(10) {
+        Some(&flags) => 13 + table(flags),
+        None => return 1,
+    };
+    loop {
+        match data.get(i) {
+            Some(&0x21) => {
+                if data.get(i + 1) == Some(&0xFF) {
+                    match (data.get(i + 2..i + 14), data.get(i + 14..i + 18)) {
+                        (Some(b"\x0bNETSCAPE2.0"), Some(&[3, 1, lo, hi])) |
+                        (Some(b"\x0bANIMEXTS1.0"), Some(&[3, 1, lo, hi])) => {
+                            let n = u16::from_le_bytes([lo, hi]) as u32;
+                            return if n == 0 { 0 } else { n + 1 }
+                        },
+                        _ => (),
+                    }
+                }
+                i = blocks(i + 2);
+            },
+            Some(&0x2C) => {
+                let flags = data.get(i + 9).cloned().unwrap_or(0);
+                // The descriptor, any local color table, and the minimum code size
+                i = blocks(i + 10 + table(flags) + 1);
+            },
+            _ => return 1,
+        }
+    }
+}
+#[cfg(not(feature = "gif"))]
+fn load_gif(_: &[u8], _: Pixel) -> Result<Animation> {
+    Err(Error::Unsupported("GIF (rebuild with the gif feature)".to_string()))
+}
+// Returns nothing for PNGs which aren't animated, so they can be loaded as usual.
+fn load_apng(data: &[u8], background: Pixel) -> Result<Option<Animation>> {
+    let err = |e: ::png::DecodingError| Error::Decode(e.to_string());
+    let mut decoder = Decoder::new(data);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(_) => return Ok(None),
+    };
+    let control = match reader.info().animation_control {
+        Some(control) => control,
+        None => return Ok(None),
+    };
+    // Without a frame control before the image data, the default image isn't part of the
+    // animation and is only there for decoders which don't understand APNG
+    let skip_default = reader.info().frame_control.is_none();
+    let (width, height) = reader.info().size();
+    let mut canvas = Canvas::new(width, height);
+    let mut frames = Vec::new();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    for i in 0..control.num_frames + skip_default as u32 {
+        let info = reader.next_frame(&mut buf).map_err(err)?;
+        if i == 0 && skip_default {
+            continue
+        }
+        let fc = reader.info().frame_control.ok_or_else(|| {
+            Error::Decode("APNG frame without a frame control chunk".to_string())
+        })?;
+        let rect = (fc.x_offset, fc.y_offset, fc.width, fc.height);
+        let rgba = to_rgba(&buf[..info.buffer_size()], info.color_type.samples());
+        let previous = canvas.pixels.clone();
+        canvas.draw(&rgba, rect, fc.blend_op == BlendOp::Over);
+        let den = if fc.delay_den == 0 { 100 } else { fc.delay_den as u64 };
+        frames.push(canvas.frame(background, Duration::from_millis(fc.delay_num as u64 * 1000 / den)));
+        match fc.dispose_op {
+            DisposeOp::None => (),
+            DisposeOp::Background => canvas.clear(rect),
+            DisposeOp::Previous => canvas.pixels = previous,
+        }
+    }
+    Ok(Some(Animation { frames, loops: control.num_plays }))
+}
+// Expands 8-bit gray, gray and alpha, or RGB samples into RGBA.
+fn to_rgba(buf: &[u8], channels: usize) -> Vec<u8> {
+    buf.chunks(channels).flat_map(|p| match channels {
+        1 => [p[0], p[0], p[0], 255],
+        2 => [p[0], p[0], p[0], p[1]],
+        3 => [p[0], p[1], p[2], 255],
+        _ => [p[0], p[1], p[2], p[3]],
+    }.to_vec()).collect()
+}
+
+#[cfg(all(test, feature = "gif"))]
+mod tests {
+    use super::*;
+
+    // A single black pixel, with these extensions before it
+    fn gif(extensions: &[u8]) -> Vec<u8> {
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff".to_vec();
+        data.extend_from_slice(extensions);
+        data.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00\x3b");
+        data
+    }
+
+    #[test]
+    fn loops() {
+        let netscape = |n: u16| {
+            let mut ext = b"\x21\xff\x0bNETSCAPE2.0\x03\x01".to_vec();
+            ext.extend_from_slice(&n.to_le_bytes());
+            ext.push(0);
+            ext
+        };
+        assert_eq!(gif_loops(&gif(&netscape(0))), 0);
+        assert_eq!(gif_loops(&gif(&netscape(2))), 3);
+        assert_eq!(gif_loops(&gif(&[])), 1);
+        // Other extensions before it are skipped over
+        let mut ext = b"\x21\xfe\x05hello\x00\x21\xff\x0bXMP DataXMP\x02ab\x00".to_vec();
+        ext.extend(netscape(5));
+        assert_eq!(gif_loops(&gif(&ext)), 6);
+        assert_eq!(gif_loops(b"GIF89a"), 1);
+        let anim = decode_animation(&gif(&netscape(0)), "test.gif", Pixel::black()).unwrap();
+        assert_eq!((anim.frames.len(), anim.loops), (1, 0));
+    }
+    #[test]
+    fn delays() {
+        assert_eq!(gif_delay(10), Duration::from_millis(100));
+        assert_eq!(gif_delay(20), Duration::from_millis(20));
+    }
+}
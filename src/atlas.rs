@@ -0,0 +1,45 @@
+// Copyright © 2016, Peter Atashian
+
+use error::{Error, Result};
+use image::{open};
+
+/// The glyphs of a font, loaded from a `{w}x{h}.png` image with the 256 characters of code page
+/// 437 laid out in a 16 by 16 grid.
+#[derive(Clone)]
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    lit: Vec<bool>,
+}
+impl Atlas {
+    /// Loads the atlas for a font with `w` by `h` cells from the current directory.
+    pub fn load(w: u32, h: u32) -> Result<Atlas> {
+        let img = open(format!("{}x{}.png", w, h)).map_err(|e| Error::MissingAtlas(w, h, e.to_string()))?.to_rgba();
+        if img.width() < 16 * w || img.height() < 16 * h {
+            let msg = format!("it is {}x{} rather than {}x{}", img.width(), img.height(), 16 * w, 16 * h);
+            return Err(Error::MissingAtlas(w, h, msg))
+        }
+        let mut lit = Vec::with_capacity((256 * w * h) as usize);
+        for i in 0..256 {
+            let (bx, by) = (i % 16 * w, i / 16 * h);
+            for y in by..(by + h) {
+                for x in bx..(bx + w) {
+                    lit.push(img.get_pixel(x, y).data[0] != 0);
+                }
+            }
+        }
+        Ok(Atlas { width: w, height: h, lit })
+    }
+    /// Builds an atlas from an eight pixel wide font stored as one byte per row with the leftmost
+    /// pixel in the high bit, as in VGA fonts.
+    pub fn from_rows(height: u32, rows: &[u8]) -> Atlas {
+        let lit = rows.iter().take((256 * height) as usize).flat_map(|&row| {
+            (0..8).map(move |x| row & 0x80 >> x != 0)
+        }).collect();
+        Atlas { width: 8, height, lit }
+    }
+    /// Whether the pixel at `x`, `y` within the glyph for `ch` is drawn in the foreground color.
+    pub fn lit(&self, ch: u8, x: u32, y: u32) -> bool {
+        self.lit[((ch as u32 * self.height + y) * self.width + x) as usize]
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright © 2016, Peter Atashian
+
+use ascii::{load_animation};
+use ascii::import::{Art};
+use std::collections::{HashSet};
+use std::fs::{canonicalize, read_dir};
+use std::io::{Result};
+use std::path::{Path, PathBuf};
+use {DEFAULT_FONT, Options, prepare, read_art, render, save};
+
+// What gets converted when no patterns are given
+const IMAGES: &[&str] = &[
+    "*.bmp", "*.exr", "*.gif", "*.hdr", "*.ico", "*.jpeg", "*.jpg", "*.pbm", "*.pgm", "*.png",
+    "*.pnm", "*.ppm", "*.tga", "*.tif", "*.tiff", "*.webp",
+];
+
+/// Converts every image in `dir` matching the include patterns, writing each one out in the
+/// chosen format. Returns how many images could not be converted.
+pub fn run(dir: &Path, opts: &Options) -> usize {
+    let mut files = Vec::new();
+    if let Err(e) = walk(dir, opts.recursive, &mut files) {
+        eprintln!("Failed to read {}: {}", dir.display(), e);
+        return 1
+    }
+    // Every image shares the same glyphs and palette
+    let font = opts.font.unwrap_or(DEFAULT_FONT);
+    let chars = match opts.chars(font) {
+        Ok(chars) => chars,
+        Err(e) => {
+            eprintln!("Failed to convert {}: {}", dir.display(), e);
+            return 1
+        },
+    };
+    let files: Vec<_> = files.into_iter().filter(|file| included(file, opts)).collect();
+    // Nothing that is being converted may be written over, including by its own output
+    let inputs: HashSet<_> = files.iter().filter_map(|file| canonicalize(file).ok()).collect();
+    let mut written = HashSet::new();
+    let mut failed = 0;
+    for file in &files {
+        let out = output_path(dir, file, opts);
+        let existing = canonicalize(&out).ok();
+        if existing.as_ref().is_some_and(|out| inputs.contains(out)) {
+            let what = if existing == canonicalize(file).ok() { "the image itself" } else { "another image being converted" };
+            eprintln!("Failed to convert {}: the output would replace {}, so use --out-dir or another format", file.display(), what);
+            failed += 1;
+            continue
+        }
+        if let Some(ref existing) = existing {
+            if written.contains(existing) {
+                eprintln!("Failed to convert {}: {} was already written from another image", file.display(), out.display());
+                failed += 1;
+                continue
+            }
+            eprintln!("Overwriting {}", out.display());
+        }
+        let result = match read_art(file, opts) {
+            // Art is saved as it is rather than being converted again
+            Some(art) => art.and_then(|art| save(&out, art, opts)),
+            None => load_animation(&file.to_string_lossy(), opts.background).and_then(|anim| {
+                // Only the first frame of an animation makes it into the file
+                let img = prepare(anim.frames.into_iter().next().unwrap().image, opts)?;
+                let (cells, width, _) = render(img, font, &chars, opts)?;
+                let art = Art { cells, width, palette: None, font: None };
+                save(&out, art, opts)
+            }),
+        };
+        match result {
+            Ok(()) => written.extend(canonicalize(&out).ok()),
+            Err(e) => {
+                eprintln!("Failed to convert {}: {}", file.display(), e);
+                failed += 1;
+            },
+        }
+    }
+    failed
+}
+// Collects the files in `dir` in a stable order, descending into subdirectories if asked to
+fn walk(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                walk(&path, recursive, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+fn included(file: &Path, opts: &Options) -> bool {
+    let name: Vec<char> = match file.file_name() {
+        Some(name) => name.to_string_lossy().chars().collect(),
+        None => return false,
+    };
+    let matches = |pattern: &str| glob(&pattern.chars().collect::<Vec<_>>(), &name);
+    if opts.include.is_empty() {
+        IMAGES.iter().any(|pattern| matches(pattern))
+    } else {
+        opts.include.iter().any(|pattern| matches(pattern))
+    }
+}
+/// Matches a file name against a pattern where `*` stands for any run of characters and `?` for
+/// any one character, ignoring ASCII case.
+fn glob(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..])),
+        (Some('?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p.eq_ignore_ascii_case(n) => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+// The output goes next to the image, or mirrors the directory layout under the output directory
+fn output_path(dir: &Path, file: &Path, opts: &Options) -> PathBuf {
+    let path = match opts.out_dir {
+        Some(ref out_dir) => out_dir.join(file.strip_prefix(dir).unwrap_or(file)),
+        None => file.to_path_buf(),
+    };
+    path.with_extension(opts.format.extension())
+}
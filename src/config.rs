@@ -0,0 +1,206 @@
+// Copyright © 2016, Peter Atashian
+
+use ascii::{Mode, Weights};
+use serde::{Deserialize};
+use std::collections::{HashMap};
+use std::env::{current_dir, var_os};
+use std::fs::{read_to_string};
+use std::io::{ErrorKind};
+use std::mem::{take};
+use std::path::{Path, PathBuf};
+use toml;
+use {characters, palette, size, weights};
+
+/// Settings which can be given defaults in a configuration file or be grouped into a named
+/// preset, as well as being given on the command line. Anything left unset falls back to what
+/// comes before it.
+#[derive(Clone, Default)]
+pub struct Preset {
+    pub mode: Option<Mode>,
+    pub palette: Option<[u32; 16]>,
+    pub charset: Option<Vec<u8>>,
+    pub font: Option<(u32, u32)>,
+    pub dither: Option<bool>,
+    pub weights: Option<Weights>,
+}
+impl Preset {
+    /// Lays `other` over this one, so that whatever it sets wins.
+    pub fn merge(self, other: Preset) -> Preset {
+        Preset {
+            mode: other.mode.or(self.mode),
+            palette: other.palette.or(self.palette),
+            charset: other.charset.or(self.charset),
+            font: other.font.or(self.font),
+            dither: other.dither.or(self.dither),
+            weights: other.weights.or(self.weights),
+        }
+    }
+}
+// A preset as it is written in a configuration file, before the values have been checked
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawPreset {
+    mode: Option<Mode>,
+    palette: Option<Vec<String>>,
+    charset: Option<String>,
+    font: Option<String>,
+    dither: Option<bool>,
+    weights: Option<Weights>,
+}
+impl RawPreset {
+    fn check(self) -> Result<Preset, String> {
+        let palette = match self.palette {
+            Some(colors) => Some(palette("palette", &colors.iter().map(|c| &**c).collect::<Vec<_>>())?),
+            None => None,
+        };
+        Ok(Preset {
+            mode: self.mode,
+            palette,
+            charset: self.charset.map(|s| characters("charset", &s)).transpose()?,
+            font: self.font.map(|s| size("font", &s)).transpose()?,
+            dither: self.dither,
+            weights: self.weights.map(|w| weights("weights", w)).transpose()?,
+        })
+    }
+}
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    defaults: RawPreset,
+    presets: HashMap<String, RawPreset>,
+}
+/// The defaults and presets from the user's configuration file and then the project's. The
+/// project's defaults are laid over the user's, and its presets replace any of the same name.
+#[derive(Default)]
+pub struct Config {
+    defaults: Preset,
+    presets: HashMap<String, Preset>,
+}
+impl Config {
+    /// Reads whichever of the configuration files there are.
+    pub fn load() -> Result<Config, String> {
+        let mut config = Config::default();
+        for path in user_config().into_iter().chain(project_config()) {
+            config.read(&path)?;
+        }
+        Ok(config)
+    }
+    fn read(&mut self, path: &Path) -> Result<(), String> {
+        let text = match read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        };
+        self.add(&text, path)
+    }
+    // Lays the configuration in `text`, which came from `path`, over what has been read so far
+    fn add(&mut self, text: &str, path: &Path) -> Result<(), String> {
+        let raw: RawConfig = toml::from_str(text).map_err(|e| format!("in {}: {}", path.display(), e))?;
+        let defaults = raw.defaults.check().map_err(|e| format!("in the defaults in {}: {}", path.display(), e))?;
+        self.defaults = take(&mut self.defaults).merge(defaults);
+        for (name, preset) in raw.presets {
+            let preset = preset.check().map_err(|e| {
+                format!("in preset `{}` in {}: {}", name, path.display(), e)
+            })?;
+            self.presets.insert(name, preset);
+        }
+        Ok(())
+    }
+    /// The defaults with the named preset laid over them.
+    pub fn resolve(&self, preset: Option<&str>) -> Result<Preset, String> {
+        let defaults = self.defaults.clone();
+        match preset {
+            Some(name) => match self.presets.get(name) {
+                Some(preset) => Ok(defaults.merge(preset.clone())),
+                None => Err(format!("unknown preset `{}`", name)),
+            },
+            None => Ok(defaults),
+        }
+    }
+}
+// Where the user's configuration lives, following the conventions of each platform
+fn user_config() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        var_os("APPDATA").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+    } else {
+        var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from).or_else(|| {
+            var_os("HOME").map(|home| Path::new(&home).join(".config"))
+        })
+    };
+    dir.map(|dir| dir.join("ascii").join("config.toml"))
+}
+// The project's configuration is the nearest `ascii.toml` in the current directory or above it
+fn project_config() -> Option<PathBuf> {
+    let dir = current_dir().ok()?;
+    dir.ancestors().map(|dir| dir.join("ascii.toml")).find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(files: &[&str]) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (i, text) in files.iter().enumerate() {
+            config.add(text, Path::new(&format!("{}.toml", i)))?;
+        }
+        Ok(config)
+    }
+
+    #[test]
+    fn precedence() {
+        let config = config(&[r#"
+            [defaults]
+            mode = "grayscale"
+            dither = false
+            font = "8x8"
+            [presets.mono]
+            mode = "monochrome"
+            charset = " #"
+        "#]).unwrap();
+        let cli = Preset { charset: Some(vec![b'.']), ..Preset::default() };
+        let preset = config.resolve(Some("mono")).unwrap().merge(cli);
+        assert_eq!(preset.mode, Some(Mode::Monochrome));
+        assert_eq!(preset.dither, Some(false));
+        assert_eq!(preset.font, Some((8, 8)));
+        assert_eq!(preset.charset, Some(vec![b'.']));
+        let preset = config.resolve(None).unwrap();
+        assert_eq!(preset.mode, Some(Mode::Grayscale));
+        assert_eq!(preset.charset, None);
+    }
+    #[test]
+    fn project_over_user() {
+        let config = config(&[r#"
+            [defaults]
+            mode = "color"
+            dither = false
+            [presets.small]
+            font = "4x4"
+            dither = true
+        "#, r#"
+            [defaults]
+            mode = "grayscale"
+            [presets.small]
+            font = "8x8"
+        "#]).unwrap();
+        let defaults = config.resolve(None).unwrap();
+        assert_eq!((defaults.mode, defaults.dither), (Some(Mode::Grayscale), Some(false)));
+        // The user's preset is replaced rather than laid under the project's
+        let small = config.resolve(Some("small")).unwrap();
+        assert_eq!((small.font, small.dither), (Some((8, 8)), Some(false)));
+    }
+    #[test]
+    fn unknown_preset() {
+        let config = config(&["[presets.a]\ndither = true"]).unwrap();
+        assert!(config.resolve(Some("a")).is_ok());
+        assert_eq!(config.resolve(Some("b")).err(), Some("unknown preset `b`".to_string()));
+    }
+    #[test]
+    fn invalid_weights() {
+        let e = config(&["[defaults]\nweights = { color = -1.0, contrast = 1.0 }"]).err().unwrap();
+        assert!(e.contains("weights can't be negative"), "{}", e);
+        let e = config(&["[presets.a]\nweights = { color = nan, contrast = 1.0 }"]).err().unwrap();
+        assert!(e.starts_with("in preset `a` in 0.toml"), "{}", e);
+        assert!(config(&["[defaults]\nweights = \"1,1\""]).is_err());
+    }
+}
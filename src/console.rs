@@ -0,0 +1,169 @@
+// Copyright © 2016, Peter Atashian
+
+use ascii::{Animation, CHARS, Cell, Char, Error, Frame, Image, Result, convert};
+use std::io::{self};
+use std::thread::{sleep};
+use std::time::{Duration, Instant};
+use wio::console::{CharInfo, Input, InputBuffer, ScreenBuffer, ScreenBufferInfoEx};
+use {Options, fit_image};
+
+// A console buffer of our own to draw on, which is swapped back out when done
+struct Screen {
+    orig: ScreenBuffer,
+    orig_info: ScreenBufferInfoEx,
+    cout: ScreenBuffer,
+    cin: InputBuffer,
+    window: (u32, u32),
+    font: (u32, u32),
+    size: Option<(u32, u32)>,
+}
+impl Screen {
+    fn new(opts: &Options) -> Result<Screen> {
+        // Back up console colors
+        let orig = ScreenBuffer::from_conout().map_err(backend)?;
+        let orig_info = orig.info_ex().map_err(backend)?;
+        // Measure the window we were started from
+        let window = {
+            let mut info = orig.info_ex().map_err(backend)?;
+            let rect = info.raw_mut().srWindow;
+            ((rect.Right - rect.Left + 1) as u32, (rect.Bottom - rect.Top + 1) as u32)
+        };
+        // Create a new console buffer
+        let cout = ScreenBuffer::new().map_err(backend)?;
+        // Calculate some dimensions
+        let font = match opts.font {
+            Some(font) => font,
+            None => {
+                let (fw, fh) = cout.font_size().map_err(backend)?;
+                (fw as u32, fh as u32)
+            },
+        };
+        let cin = InputBuffer::from_conin().map_err(backend)?;
+        cin.flush_input().map_err(backend)?;
+        Ok(Screen { orig, orig_info, cout, cin, window, font, size: None })
+    }
+    fn convert(&self, img: Image, chars: &[(Vec<Char>, f32)], opts: &Options) -> Result<(Vec<CharInfo>, (u32, u32))> {
+        let img = fit_image(img, self.font, opts, Some(self.window));
+        let size = (img.width, img.height);
+        Ok((char_infos(&convert(&opts.settings(), img, chars)?), size))
+    }
+    fn show(&mut self, text: &[CharInfo], (w, h): (u32, u32), table: [u32; 16]) -> Result<()> {
+        // Setup the console buffer info the first time around
+        if self.size.is_none() {
+            let mut info = self.cout.info_ex().map_err(backend)?;
+            {
+                let rinfo = info.raw_mut();
+                rinfo.ColorTable = table;
+                rinfo.dwSize.X = w as i16;
+                rinfo.dwSize.Y = h as i16;
+                rinfo.srWindow.Right = w as i16;
+                rinfo.srWindow.Bottom = h as i16;
+                rinfo.dwMaximumWindowSize.X = w as i16;
+                rinfo.dwMaximumWindowSize.Y = h as i16;
+            }
+            self.cout.set_active().map_err(backend)?;
+            self.cout.set_info_ex(info).map_err(backend)?;
+            self.size = Some((w, h));
+        }
+        self.cout.write_output(text, (w as i16, h as i16), (0, 0)).map_err(backend)?;
+        Ok(())
+    }
+    // Checks whether enter has been pressed, waiting up to `timeout` for it if given
+    fn enter(&self, timeout: Option<Duration>) -> Result<bool> {
+        let start = Instant::now();
+        loop {
+            if timeout.is_none() || self.cin.available_input().map_err(backend)? > 0 {
+                for input in self.cin.read_input().map_err(backend)? {
+                    if let Input::Key{key_code: 0x0D, ..} = input { return Ok(true) }
+                }
+            }
+            match timeout {
+                Some(timeout) if start.elapsed() >= timeout => return Ok(false),
+                Some(_) => sleep(Duration::from_millis(10)),
+                None => (),
+            }
+        }
+    }
+    // Puts the original buffer back, passing on what happened while ours was up
+    fn restore<T>(self, result: Result<T>) -> Result<T> {
+        // Restore console colors
+        self.orig.set_info_ex(self.orig_info).map_err(backend)?;
+        self.orig.set_active().map_err(backend)?;
+        result
+    }
+}
+fn backend(e: io::Error) -> Error {
+    Error::Backend(format!("console: {}", e))
+}
+fn char_infos(text: &[Cell]) -> Vec<CharInfo> {
+    text.iter().map(|cell| CharInfo::new(CHARS[cell.ch as usize], cell.attr as u16)).collect()
+}
+pub fn display(anim: Animation, opts: &Options) -> Result<()> {
+    let mut screen = Screen::new(opts)?;
+    let result = play(&mut screen, anim, opts);
+    screen.restore(result)
+}
+fn play(screen: &mut Screen, anim: Animation, opts: &Options) -> Result<()> {
+    // Figure out characters
+    let chars = opts.chars(screen.font)?;
+    // Convert every frame up front so playback isn't held up by the conversion
+    let frames = anim.frames.into_iter().map(|frame| {
+        let (text, size) = screen.convert(frame.image, &chars, opts)?;
+        Ok((text, size, frame.delay))
+    }).collect::<Result<Vec<_>>>()?;
+    // Display image, playing it through if animated, until enter is pressed
+    if frames.len() == 1 {
+        screen.show(&frames[0].0, frames[0].1, opts.table())?;
+        screen.enter(None)?;
+    } else {
+        let mut plays = 0;
+        'done: while anim.loops == 0 || plays < anim.loops {
+            for &(ref text, size, delay) in &frames {
+                screen.show(text, size, opts.table())?;
+                if screen.enter(Some(delay))? { break 'done }
+            }
+            plays += 1;
+        }
+        // Leave the last frame up once the animation has finished
+        if anim.loops != 0 && plays == anim.loops {
+            screen.enter(None)?;
+        }
+    }
+    Ok(())
+}
+/// Plays frames as they arrive, skipping any which would already be late by the time they were
+/// converted. Pressing enter stops playback early.
+pub fn stream<I: Iterator<Item = Frame>>(frames: I, opts: &Options) -> Result<()> {
+    let mut screen = Screen::new(opts)?;
+    let result = play_stream(&mut screen, frames, opts);
+    screen.restore(result)
+}
+fn play_stream<I: Iterator<Item = Frame>>(screen: &mut Screen, frames: I, opts: &Options) -> Result<()> {
+    let chars = opts.chars(screen.font)?;
+    let start = Instant::now();
+    let mut due = Duration::from_secs(0);
+    for frame in frames {
+        due += frame.delay;
+        if start.elapsed() > due {
+            continue
+        }
+        let (text, size) = screen.convert(frame.image, &chars, opts)?;
+        screen.show(&text, size, opts.table())?;
+        let wait = (start + due).checked_duration_since(Instant::now());
+        if screen.enter(Some(wait.unwrap_or_default()))? {
+            return Ok(())
+        }
+    }
+    // Leave the last frame up once the stream has ended
+    screen.enter(None)?;
+    Ok(())
+}
+/// Shows cells which have already been converted in the colors from `table`, waiting for enter as
+/// with still images.
+pub fn show_text(text: &[Cell], w: u32, table: &[u32; 16], opts: &Options) -> Result<()> {
+    let mut screen = Screen::new(opts)?;
+    let result = screen.show(&char_infos(text), (w, text.len() as u32 / w), *table).and_then(|()| {
+        screen.enter(None).map(|_| ())
+    });
+    screen.restore(result)
+}
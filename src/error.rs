@@ -0,0 +1,115 @@
+// Copyright © 2016, Peter Atashian
+
+use image::{ImageError};
+use png::{EncodingError};
+use serde_json;
+use std::error::{Error as StdError};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+
+/// Everything that can go wrong between reading an image and showing or saving it.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or stream failed.
+    Io(io::Error),
+    /// An image, animation, video or art file was malformed.
+    Decode(String),
+    /// The data is in a format this build can't read.
+    Unsupported(String),
+    /// The glyph atlas for a font of this width and height couldn't be loaded.
+    MissingAtlas(u32, u32, String),
+    /// A size was zero or didn't agree with the data it was given for.
+    InvalidDimensions(String),
+    /// There were no characters to choose from when converting.
+    NoGlyphs,
+    /// The console, terminal or system couldn't provide something that was needed.
+    Backend(String),
+}
+/// The result of anything which can fail with an `Error`.
+pub type Result<T> = ::std::result::Result<T, Error>;
+impl Error {
+    /// Whether this is a write to a pipe whose reader went away, which is best ended quietly.
+    pub fn is_broken_pipe(&self) -> bool {
+        match *self {
+            Error::Io(ref e) => e.kind() == ErrorKind::BrokenPipe,
+            _ => false,
+        }
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Decode(ref msg) => write!(f, "{}", msg),
+            Error::Unsupported(ref msg) => write!(f, "unsupported format: {}", msg),
+            Error::MissingAtlas(w, h, ref msg) => write!(f, "couldn't load the font atlas {}x{}.png: {}", w, h, msg),
+            Error::InvalidDimensions(ref msg) => write!(f, "invalid dimensions: {}", msg),
+            Error::NoGlyphs => write!(f, "there are no characters to choose from"),
+            Error::Backend(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Error {
+        match e {
+            ImageError::IoError(e) => Error::Io(e),
+            ImageError::UnsupportedError(msg) => Error::Unsupported(msg),
+            ImageError::DimensionError => Error::InvalidDimensions(e.to_string()),
+            e => Error::Decode(e.to_string()),
+        }
+    }
+}
+impl From<EncodingError> for Error {
+    fn from(e: EncodingError) -> Error {
+        match e {
+            EncodingError::IoError(e) => Error::Io(e),
+            // Which are all about the size of the image and how much of it was written
+            EncodingError::Format(_) | EncodingError::LimitsExceeded => Error::InvalidDimensions(e.to_string()),
+            EncodingError::Parameter(_) => Error::Backend(e.to_string()),
+        }
+    }
+}
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        if e.is_io() {
+            Error::Io(e.into())
+        } else {
+            Error::Backend(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quality::{write_heatmap};
+
+    #[test]
+    fn encoding() {
+        let e = write_heatmap(&mut Vec::new(), &[], 0, (8, 16)).unwrap_err();
+        assert!(matches!(e, Error::InvalidDimensions(_)), "{:?}", e);
+        assert!(matches!(Error::from(EncodingError::LimitsExceeded), Error::InvalidDimensions(_)));
+        let e = EncodingError::IoError(io::Error::new(ErrorKind::BrokenPipe, "gone"));
+        assert!(Error::from(e).is_broken_pipe());
+    }
+    #[test]
+    fn json() {
+        let e = serde_json::from_str::<u32>("x").unwrap_err();
+        assert!(matches!(Error::from(e), Error::Backend(_)));
+        let e = serde_json::Error::io(io::Error::new(ErrorKind::BrokenPipe, "gone"));
+        assert!(Error::from(e).is_broken_pipe());
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright © 2016, Peter Atashian
+
+use error::{Error, Result};
+use sauce::{DataType, Sauce, swap_red_blue};
+use {Atlas, Cell};
+
+/// Text mode art read back in from a file.
+pub struct Art {
+    pub cells: Vec<Cell>,
+    pub width: u32,
+    /// The palette stored with the art, if any, laid out like `Mode::color_table`.
+    pub palette: Option<[u32; 16]>,
+    /// The font stored with the art, if any.
+    pub font: Option<Atlas>,
+}
+impl Art {
+    // Art with nothing to go on but the cells
+    fn new(mut cells: Vec<Cell>, w: u32) -> Art {
+        // Pad out the last row
+        let rows = (cells.len() as u32).div_ceil(w);
+        cells.resize((rows * w) as usize, BLANK);
+        Art { cells, width: w, palette: None, font: None }
+    }
+}
+const BLANK: Cell = Cell { ch: b' ', attr: 0x07, transparent: false };
+
+/// Reads raw character and attribute pairs, as found in VGA text mode memory. The width comes from
+/// `width` if given, then from the SAUCE record, and is otherwise the usual 160 columns.
+pub fn read_bin(data: &[u8], width: Option<u32>) -> Art {
+    let (sauce, data) = Sauce::read(data);
+    let w = width.or_else(|| match sauce {
+        Some(ref sauce) if sauce.data_type == DataType::Binary && sauce.width > 0 => {
+            Some(sauce.width as u32)
+        },
+        _ => None,
+    }).unwrap_or(160).max(1);
+    let cells = data.chunks(2).filter(|pair| pair.len() == 2).map(|pair| {
+        Cell { ch: pair[0], attr: pair[1], transparent: false }
+    }).collect();
+    Art::new(cells, w)
+}
+/// Reads XBin art along with its palette and font, if it has them.
+pub fn read_xbin(data: &[u8]) -> Result<Art> {
+    let (_, data) = Sauce::read(data);
+    if data.len() < 11 || &data[..5] != b"XBIN\x1a" {
+        return Err(invalid("not an XBin file"))
+    }
+    let w = u16::from_le_bytes([data[5], data[6]]) as u32;
+    let h = u16::from_le_bytes([data[7], data[8]]) as usize;
+    let (font_height, flags) = (data[9] as u32, data[10]);
+    let mut rest = &data[11..];
+    let mut take = |len: usize| -> Result<&[u8]> {
+        if rest.len() < len {
+            return Err(invalid("truncated XBin file"))
+        }
+        let (taken, left) = rest.split_at(len);
+        rest = left;
+        Ok(taken)
+    };
+    let palette = if flags & 1 != 0 {
+        let mut table = [0; 16];
+        for (entry, rgb) in table.iter_mut().zip(take(48)?.chunks(3)) {
+            // Scale the six bit channels back up to eight
+            let c = |x: u8| (x as u32 & 0x3F) * 255 / 63;
+            *entry = c(rgb[0]) | c(rgb[1]) << 8 | c(rgb[2]) << 16;
+        }
+        Some(table)
+    } else {
+        None
+    };
+    let font = if flags & 2 != 0 {
+        // Fonts with 512 characters are cut down to the first 256
+        let chars = if flags & 16 != 0 { 512 } else { 256 };
+        Some(Atlas::from_rows(font_height, take(chars * font_height as usize)?))
+    } else {
+        None
+    };
+    let total = w as usize * h;
+    let mut cells = Vec::with_capacity(total);
+    if flags & 4 != 0 {
+        while cells.len() < total {
+            let run = take(1)?[0];
+            let len = (run & 0x3F) as usize + 1;
+            match run >> 6 {
+                0 => cells.extend(take(len * 2)?.chunks(2).map(|p| (p[0], p[1]))),
+                1 => {
+                    let ch = take(1)?[0];
+                    cells.extend(take(len)?.iter().map(|&attr| (ch, attr)));
+                },
+                2 => {
+                    let attr = take(1)?[0];
+                    cells.extend(take(len)?.iter().map(|&ch| (ch, attr)));
+                },
+                _ => {
+                    let cell = take(2)?;
+                    cells.extend((0..len).map(|_| (cell[0], cell[1])));
+                },
+            }
+        }
+        cells.truncate(total);
+    } else {
+        cells.extend(take(total * 2)?.chunks(2).map(|p| (p[0], p[1])));
+    }
+    let cells = cells.into_iter().map(|(ch, attr)| Cell { ch, attr, transparent: false }).collect();
+    let mut art = Art::new(cells, w.max(1));
+    art.palette = palette;
+    art.font = font;
+    Ok(art)
+}
+/// Reads ANSI art, drawing it the way ANSI.SYS would on a screen `width` columns wide. The width
+/// comes from the SAUCE record if not given, and is otherwise 80 columns. Bright backgrounds are
+/// always shown as such, rather than blinking.
+pub fn read_ansi(data: &[u8], width: Option<u32>) -> Art {
+    let (sauce, data) = Sauce::read(data);
+    let w = width.or_else(|| match sauce {
+        Some(ref sauce) if sauce.data_type == DataType::Ansi && sauce.width > 0 => {
+            Some(sauce.width as u32)
+        },
+        _ => None,
+    }).unwrap_or(80).max(1) as usize;
+    let mut screen = Screen { cells: Vec::new(), w, rows: 0 };
+    let (mut x, mut y, mut saved): (usize, usize, _) = (0, 0, (0, 0));
+    // Whether the cursor has just wrapped onto a new row
+    let mut wrapped = false;
+    let (mut fg, mut bg, mut bold, mut blink, mut inverse) = (7, 0, false, false, false);
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        match byte {
+            // Anything after the end of file marker is just metadata
+            0x1A => break,
+            b'\r' => x = 0,
+            b'\n' => {
+                // Art drawn for a screen this wide often ends full rows with a line break as
+                // well, which shouldn't leave an empty row behind
+                if !wrapped {
+                    y += 1;
+                }
+                x = 0;
+                wrapped = false;
+            },
+            b'\t' => {
+                x = ((x / 8 + 1) * 8).min(w - 1);
+                wrapped = false;
+            },
+            0x1B if data.get(i) == Some(&b'[') => {
+                // Gather up the parameters until the final letter of the sequence
+                let start = i + 1;
+                let end = match data[start..].iter().position(|&b| (0x40..0x7F).contains(&b)) {
+                    Some(end) => start + end,
+                    None => break,
+                };
+                i = end + 1;
+                // Numbers are capped well past the size of any real screen
+                let params: Vec<usize> = data[start..end].split(|&b| b == b';').map(|p| {
+                    p.iter().filter(|b| b.is_ascii_digit()).fold(0, |n, &b| (n * 10 + (b - b'0') as usize).min(9999))
+                }).collect();
+                let n = params.first().cloned().filter(|&n| n > 0).unwrap_or(1);
+                match data[end] {
+                    b'A' => y = y.saturating_sub(n),
+                    b'B' => y += n,
+                    b'C' => x = (x + n).min(w - 1),
+                    b'D' => x = x.saturating_sub(n),
+                    b'H' | b'f' => {
+                        y = params.first().cloned().unwrap_or(1).max(1) - 1;
+                        x = (params.get(1).cloned().unwrap_or(1).max(1) - 1).min(w - 1);
+                    },
+                    b'J' if params.first() == Some(&2) => {
+                        screen.cells.clear();
+                        screen.rows = 0;
+                        x = 0;
+                        y = 0;
+                    },
+                    b'K' => {
+                        for xx in x..w {
+                            screen.put(xx, y, b' ', 0x07);
+                        }
+                    },
+                    b's' => saved = (x, y),
+                    b'u' => {
+                        x = saved.0;
+                        y = saved.1;
+                    },
+                    b'm' => for &p in &params {
+                        match p {
+                            0 => {
+                                fg = 7;
+                                bg = 0;
+                                bold = false;
+                                blink = false;
+                                inverse = false;
+                            },
+                            1 => bold = true,
+                            5 => blink = true,
+                            7 => inverse = true,
+                            22 => bold = false,
+                            25 => blink = false,
+                            27 => inverse = false,
+                            30..=37 => fg = p - 30,
+                            39 => fg = 7,
+                            40..=47 => bg = p - 40,
+                            49 => bg = 0,
+                            _ => (),
+                        }
+                    },
+                    _ => (),
+                }
+                // Colors may change between wrapping and the line break, but nothing else
+                if data[end] != b'm' {
+                    wrapped = false;
+                }
+            },
+            ch => {
+                let (f, b) = if inverse { (bg, fg) } else { (fg, bg) };
+                let attr = swap_red_blue(f as u8) | (bold as u8) << 3 | (swap_red_blue(b as u8) | (blink as u8) << 3) << 4;
+                screen.put(x, y, ch, attr);
+                x += 1;
+                wrapped = x == w;
+                if wrapped {
+                    x = 0;
+                    y += 1;
+                }
+            },
+        }
+    }
+    screen.cells.truncate(screen.rows * w);
+    Art::new(screen.cells, w as u32)
+}
+// The screen that ANSI art gets drawn on, which grows downwards as needed up to `MAX_CELLS`
+struct Screen {
+    cells: Vec<Cell>,
+    w: usize,
+    // How many rows have anything drawn on them
+    rows: usize,
+}
+impl Screen {
+    // Anything drawn past the last row there is room for is dropped
+    fn put(&mut self, x: usize, y: usize, ch: u8, attr: u8) {
+        if y >= (MAX_CELLS / self.w).max(1) {
+            return
+        }
+        let i = y * self.w + x;
+        if self.cells.len() <= i {
+            self.cells.resize((y + 1) * self.w, BLANK);
+        }
+        self.cells[i] = Cell { ch, attr, transparent: false };
+        self.rows = self.rows.max(y + 1);
+    }
+}
+const MAX_CELLS: usize = 1 << 24;
+fn invalid(msg: &str) -> Error {
+    Error::Decode(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The characters of each row
+    fn rows(art: &Art) -> Vec<String> {
+        art.cells.chunks(art.width as usize).map(|row| row.iter().map(|cell| cell.ch as char).collect()).collect()
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(rows(&read_ansi(b"abcdefg\r\nhi", Some(4))), ["abcd", "efg ", "hi  "]);
+        // A line break straight after a wrap only ends the row once
+        assert_eq!(rows(&read_ansi(b"abcd\x1b[0m\r\nef", Some(4))), ["abcd", "ef  "]);
+        assert_eq!(rows(&read_ansi(b"abcd\r\n\r\nef", Some(4))), ["abcd", "    ", "ef  "]);
+        assert_eq!(rows(&read_ansi(b"abcd\x1b[Kef\r\n", Some(4))), ["abcd", "ef  "]);
+    }
+    #[test]
+    fn tabs() {
+        assert_eq!(rows(&read_ansi(b"a\tb\tc", Some(12))), ["a       b  c"]);
+        let art = read_ansi(b"\x1b[5;76H\t\tabc", None);
+        assert_eq!(art.cells.len(), 6 * 80);
+        assert_eq!(&rows(&art)[4][76..], "   a");
+        assert_eq!(&rows(&art)[5][..3], "bc ");
+    }
+    #[test]
+    fn cursor() {
+        assert_eq!(rows(&read_ansi(b"\x1b[2;3Hx\x1b[99Ay\x1b[99Cz\x1b[9Dw", Some(4))), ["   y", "  xz", "w   "]);
+        assert_eq!(rows(&read_ansi(b"\x1b[;9Hx\x1b[sab\x1b[u\x1b[Bc", Some(4))), ["   x", "ab  ", "c   "]);
+        // Numbers too big for any screen are capped instead of overflowing
+        let art = read_ansi(b"\x1b[99999999999999999999999A\x1b[99999999999999999999999;1Hx", Some(80));
+        assert_eq!(art.cells.len(), 9999 * 80);
+        assert_eq!(art.cells[9998 * 80].ch, b'x');
+        // and moving far enough down runs out of screen rather than memory
+        let mut data = b"\x1b[9999B".repeat(1000);
+        data.push(b'x');
+        assert!(read_ansi(&data, Some(80)).cells.is_empty());
+    }
+}
@@ -0,0 +1,668 @@
+// Copyright © 2016, Peter Atashian
+
+#[cfg(feature = "exr")]
+extern crate exr;
+#[cfg(feature = "gif")]
+extern crate gif;
+extern crate image;
+extern crate png;
+extern crate rand;
+extern crate serde;
+extern crate serde_json;
+
+use pixel::{Pixel, srgb_to_linear};
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::{StdRng};
+use serde::{Deserialize, Serialize};
+use std::mem::{size_of};
+use std::ops::{AddAssign, Mul};
+use std::str::{FromStr};
+
+pub use animation::{Animation, Frame, decode_animation, load_animation};
+pub use atlas::{Atlas};
+pub use error::{Error, Result};
+pub use load::{decode, load};
+pub use space::{Channels, ColorSpace, Luminance, Monochrome, Pair, Rgb};
+pub use tonemap::{ToneMap};
+pub use video::{VideoReader};
+
+mod animation;
+mod atlas;
+mod error;
+pub mod import;
+mod load;
+pub mod output;
+pub mod pixel;
+pub mod quality;
+pub mod sauce;
+mod space;
+mod tonemap;
+mod video;
+
+pub const COLORS: &[(u8, u8, u8); 16] = &[
+    (0x00, 0x00, 0x00), (0x00, 0x00, 0x80), (0x00, 0x80, 0x00), (0x00, 0x80, 0x80),
+    (0x80, 0x00, 0x00), (0x80, 0x00, 0x80), (0x80, 0x80, 0x00), (0xC0, 0xC0, 0xC0),
+    (0x80, 0x80, 0x80), (0x00, 0x00, 0xFF), (0x00, 0xFF, 0x00), (0x00, 0xFF, 0xFF),
+    (0xFF, 0x00, 0x00), (0xFF, 0x00, 0xFF), (0xFF, 0xFF, 0x00), (0xFF, 0xFF, 0xFF),
+];
+pub const GRAYSCALE: &[u8; 16] = &[
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+    0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+];
+
+pub const CHARS: &[u16; 256] = &[
+    0x0020, 0x263a, 0x263b, 0x2665, 0x2666, 0x2663, 0x2660, 0x2022,
+    0x25d8, 0x25cb, 0x25d9, 0x2642, 0x2640, 0x266a, 0x266b, 0x263c,
+    0x25ba, 0x25c4, 0x2195, 0x203c, 0x00b6, 0x00a7, 0x25ac, 0x21a8,
+    0x2191, 0x2193, 0x2192, 0x2190, 0x221f, 0x2194, 0x25b2, 0x25bc,
+    0x0020, 0x0021, 0x0022, 0x0023, 0x0024, 0x0025, 0x0026, 0x0027,
+    0x0028, 0x0029, 0x002a, 0x002b, 0x002c, 0x002d, 0x002e, 0x002f,
+    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
+    0x0038, 0x0039, 0x003a, 0x003b, 0x003c, 0x003d, 0x003e, 0x003f,
+    0x0040, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046, 0x0047,
+    0x0048, 0x0049, 0x004a, 0x004b, 0x004c, 0x004d, 0x004e, 0x004f,
+    0x0050, 0x0051, 0x0052, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057,
+    0x0058, 0x0059, 0x005a, 0x005b, 0x005c, 0x005d, 0x005e, 0x005f,
+    0x0060, 0x0061, 0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067,
+    0x0068, 0x0069, 0x006a, 0x006b, 0x006c, 0x006d, 0x006e, 0x006f,
+    0x0070, 0x0071, 0x0072, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077,
+    0x0078, 0x0079, 0x007a, 0x007b, 0x007c, 0x007d, 0x007e, 0x2302,
+    0x00c7, 0x00fc, 0x00e9, 0x00e2, 0x00e4, 0x00e0, 0x00e5, 0x00e7,
+    0x00ea, 0x00eb, 0x00e8, 0x00ef, 0x00ee, 0x00ec, 0x00c4, 0x00c5,
+    0x00c9, 0x00e6, 0x00c6, 0x00f4, 0x00f6, 0x00f2, 0x00fb, 0x00f9,
+    0x00ff, 0x00d6, 0x00dc, 0x00a2, 0x00a3, 0x00a5, 0x20a7, 0x0192,
+    0x00e1, 0x00ed, 0x00f3, 0x00fa, 0x00f1, 0x00d1, 0x00aa, 0x00ba,
+    0x00bf, 0x2310, 0x00ac, 0x00bd, 0x00bc, 0x00a1, 0x00ab, 0x00bb,
+    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x2561, 0x2562, 0x2556,
+    0x2555, 0x2563, 0x2551, 0x2557, 0x255d, 0x255c, 0x255b, 0x2510,
+    0x2514, 0x2534, 0x252c, 0x251c, 0x2500, 0x253c, 0x255e, 0x255f,
+    0x255a, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256c, 0x2567,
+    0x2568, 0x2564, 0x2565, 0x2559, 0x2558, 0x2552, 0x2553, 0x256b,
+    0x256a, 0x2518, 0x250c, 0x2588, 0x2584, 0x258c, 0x2590, 0x2580,
+    0x03b1, 0x00df, 0x0393, 0x03c0, 0x03a3, 0x03c3, 0x00b5, 0x03c4,
+    0x03a6, 0x0398, 0x03a9, 0x03b4, 0x221e, 0x03c6, 0x03b5, 0x2229,
+    0x2261, 0x00b1, 0x2265, 0x2264, 0x2320, 0x2321, 0x00f7, 0x2248,
+    0x00b0, 0x2219, 0x00b7, 0x221a, 0x207f, 0x00b2, 0x25a0, 0x00a0,
+];
+
+#[derive(Clone)]
+pub struct Image {
+    pub pixels: Vec<Pixel>,
+    /// How much of each pixel is covered by the image, as opposed to the background.
+    pub alpha: Vec<f32>,
+    /// What transparent parts of the image were composited over.
+    pub background: Pixel,
+    pub width: u32,
+    pub height: u32,
+}
+impl Image {
+    pub fn from_srgb(img: &[(u8, u8, u8)], width: u32, height: u32) -> Image {
+        let pixels = img.iter().map(|p| {
+            Pixel::from_srgb(p.0, p.1, p.2)
+        }).collect();
+        Image {
+            pixels,
+            alpha: vec![1.; img.len()],
+            background: Pixel::black(),
+            width,
+            height,
+        }
+    }
+    /// Composites the image over `background` in linear light.
+    pub fn from_srgba(img: &[(u8, u8, u8, u8)], width: u32, height: u32, background: Pixel) -> Image {
+        let alpha: Vec<f32> = img.iter().map(|p| p.3 as f32 / 255.).collect();
+        let pixels = img.iter().zip(&alpha).map(|(p, &a)| {
+            Pixel::from_srgb(p.0, p.1, p.2) * a + background * (1. - a)
+        }).collect();
+        Image {
+            pixels,
+            alpha,
+            background,
+            width,
+            height,
+        }
+    }
+    /// Builds an image from interleaved sRGB samples between zero and one, with one channel for
+    /// gray, two for gray and alpha, three for RGB, or four for RGBA. The samples are decoded
+    /// exactly rather than through a table, so no precision is lost.
+    pub fn from_samples(samples: &[f32], channels: usize, width: u32, height: u32, background: Pixel) -> Image {
+        Image::from_channels(samples, channels, width, height, background, srgb_to_linear)
+    }
+    /// Like `from_samples` but for samples which are already in linear light, such as those from
+    /// HDR formats. Colors may be brighter than white until they are tone mapped.
+    pub fn from_linear(samples: &[f32], channels: usize, width: u32, height: u32, background: Pixel) -> Image {
+        Image::from_channels(samples, channels, width, height, background, |x| x)
+    }
+    fn from_channels<F>(samples: &[f32], channels: usize, width: u32, height: u32, background: Pixel, decode: F) -> Image
+        where F: Fn(f32) -> f32
+    {
+        let alpha: Vec<f32> = samples.chunks(channels).map(|p| match channels {
+            2 | 4 => p[channels - 1],
+            _ => 1.,
+        }).collect();
+        let pixels = samples.chunks(channels).zip(&alpha).map(|(p, &a)| {
+            let p = if channels < 3 {
+                Pixel::new(p[0], p[0], p[0])
+            } else {
+                Pixel::new(p[0], p[1], p[2])
+            };
+            p.map(&decode) * a + background * (1. - a)
+        }).collect();
+        Image {
+            pixels,
+            alpha,
+            background,
+            width,
+            height,
+        }
+    }
+    /// Scales every color by `exposure` stops and brings those brighter than white back into
+    /// range. Images which were already in range are changed too, unless `op` is `Clamp` and
+    /// `exposure` is zero.
+    pub fn tone_map(&mut self, op: ToneMap, exposure: f32) {
+        for p in &mut self.pixels {
+            *p = op.apply(*p, exposure);
+        }
+    }
+    pub fn increase_size(&self, nw: u32, nh: u32) -> Image {
+        self.region((0, 0, nw, nh))
+    }
+    pub fn resize(&self, nw: u32, nh: u32) -> Image {
+        self.resample((0., 0., self.width as f32, self.height as f32), nw, nh)
+    }
+    /// Cuts out the `w` by `h` region with its top left corner at `x`, `y`. Whatever part of the
+    /// region lies outside of the image is left as transparent background.
+    pub fn crop(&self, x: i32, y: i32, w: u32, h: u32) -> Result<Image> {
+        let bytes = (w as usize).checked_mul(h as usize).and_then(|n| n.checked_mul(size_of::<Pixel>()));
+        if bytes.is_none_or(|bytes| bytes > isize::MAX as usize) {
+            return Err(Error::InvalidDimensions(format!("{}x{} is too big to crop to", w, h)))
+        }
+        Ok(self.region((x as i64, y as i64, w, h)))
+    }
+    fn region(&self, rect: (i64, i64, u32, u32)) -> Image {
+        Image {
+            pixels: crop_plane(&self.pixels, self.width, self.height, rect, self.background),
+            alpha: crop_plane(&self.alpha, self.width, self.height, rect, 0.),
+            background: self.background,
+            width: rect.2,
+            height: rect.3,
+        }
+    }
+    /// Magnifies the image by `factor` around its center moved by `pan` pixels, keeping the
+    /// same size. A factor below one zooms out, surrounding the image with background.
+    pub fn zoom(&self, factor: f32, pan: (i32, i32)) -> Image {
+        let (w, h) = (self.width as f32 / factor, self.height as f32 / factor);
+        let x = (self.width as f32 - w) / 2. + pan.0 as f32;
+        let y = (self.height as f32 - h) / 2. + pan.1 as f32;
+        self.resample((x, y, w, h), self.width, self.height)
+    }
+    // Scales the region `x`, `y`, `w`, `h` to `nw` by `nh`. Whatever part of the region lies
+    // outside of the image is filled with transparent background.
+    fn resample(&self, (x, y, w, h): (f32, f32, f32, f32), nw: u32, nh: u32) -> Image {
+        let xs = area_weights(self.width, x, w, nw);
+        let ys = area_weights(self.height, y, h, nh);
+        Image {
+            pixels: resize_plane(&self.pixels, self.width, self.height, &xs, &ys, Pixel::black(), self.background),
+            alpha: resize_plane(&self.alpha, self.width, self.height, &xs, &ys, 0., 0.),
+            background: self.background,
+            width: nw,
+            height: nh,
+        }
+    }
+    pub fn shrink_factor(&self, fw: u32, fh: u32) -> Image {
+        Image {
+            pixels: shrink_plane(&self.pixels, self.width, self.height, fw, fh, Pixel::black()),
+            alpha: shrink_plane(&self.alpha, self.width, self.height, fw, fh, 0.),
+            background: self.background,
+            width: self.width / fw,
+            height: self.height / fh,
+        }
+    }
+}
+// The operations on images are done a plane at a time, so that colors and alpha go through
+// exactly the same steps.
+fn crop_plane<T: Copy>(src: &[T], sw: u32, sh: u32, (x, y, w, h): (i64, i64, u32, u32), fill: T) -> Vec<T> {
+    let mut buf = vec![fill; w as usize * h as usize];
+    // The part of the region which overlaps the image
+    let (x0, y0) = (x.max(0), y.max(0));
+    let x1 = (x + w as i64).min(sw as i64);
+    let y1 = (y + h as i64).min(sh as i64);
+    if x0 < x1 {
+        for yy in y0..y1 {
+            let start = yy as usize * sw as usize;
+            let orig = &src[start + x0 as usize..start + x1 as usize];
+            let start = (yy - y) as usize * w as usize + (x0 - x) as usize;
+            buf[start..start + orig.len()].copy_from_slice(orig);
+        }
+    }
+    buf
+}
+fn shrink_plane<T>(src: &[T], sw: u32, sh: u32, fw: u32, fh: u32, zero: T) -> Vec<T>
+    where T: Copy + AddAssign + Mul<f32, Output = T>
+{
+    let mult = 1. / ((fw * fh) as f32);
+    let (nw, nh) = (sw / fw, sh / fh);
+    let mut buf = vec![zero; (nw * nh) as usize];
+    for y in 0..nh {
+        for x in 0..nw {
+            let (bx, by) = (x * fw, y * fh);
+            let mut p = zero;
+            for yy in by..(by + fh) {
+                for xx in bx..(bx + fw) {
+                    p += src[(yy * sw + xx) as usize];
+                }
+            }
+            buf[(y * nw + x) as usize] = p * mult;
+        }
+    }
+    buf
+}
+// Whatever part of each destination pixel the weights leave uncovered is made up with `fill`.
+fn resize_plane<T>(src: &[T], sw: u32, sh: u32, xs: &[Vec<(u32, f32)>], ys: &[Vec<(u32, f32)>], zero: T, fill: T) -> Vec<T>
+    where T: Copy + AddAssign + Mul<f32, Output = T>
+{
+    let (nw, nh) = (xs.len(), ys.len());
+    let mut tmp = vec![zero; nw * sh as usize];
+    for y in 0..sh as usize {
+        for (x, weights) in xs.iter().enumerate() {
+            let mut p = zero;
+            for &(xx, m) in weights {
+                p += src[y * sw as usize + xx as usize] * m;
+            }
+            tmp[y * nw + x] = p;
+        }
+    }
+    let covered = |weights: &Vec<(u32, f32)>| weights.iter().map(|&(_, m)| m).sum::<f32>();
+    let xcover: Vec<f32> = xs.iter().map(covered).collect();
+    let mut buf = vec![zero; nw * nh];
+    for (y, weights) in ys.iter().enumerate() {
+        let ycover = covered(weights);
+        for x in 0..nw {
+            let mut p = zero;
+            for &(yy, m) in weights {
+                p += tmp[yy as usize * nw + x] * m;
+            }
+            let missing = 1. - xcover[x] * ycover;
+            if missing > 1e-6 {
+                p += fill * missing;
+            }
+            buf[y * nw + x] = p;
+        }
+    }
+    buf
+}
+// For each of `dst` pixels spread along the `len` source pixels from `offset` on one axis, which
+// of the `src` source pixels it covers and by how much. The weights of each destination pixel sum
+// to one, less whatever part of it falls outside of the source.
+fn area_weights(src: u32, offset: f32, len: f32, dst: u32) -> Vec<Vec<(u32, f32)>> {
+    let scale = len / dst as f32;
+    (0..dst).map(|i| {
+        let (start, end) = (offset + i as f32 * scale, offset + (i + 1) as f32 * scale);
+        let mut weights = Vec::new();
+        let mut s = start.max(0.) as u32;
+        while (s as f32) < end && s < src {
+            let cover = end.min((s + 1) as f32) - start.max(s as f32);
+            if cover > 0. {
+                weights.push((s, cover / scale));
+            }
+            s += 1;
+        }
+        weights
+    }).collect()
+}
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Color,
+    Grayscale,
+    Monochrome,
+}
+impl FromStr for Mode {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Mode, String> {
+        match s {
+            "color" => Ok(Mode::Color),
+            "grayscale" => Ok(Mode::Grayscale),
+            "monochrome" => Ok(Mode::Monochrome),
+            _ => Err("expected color, grayscale or monochrome".to_string()),
+        }
+    }
+}
+impl Mode {
+    pub fn color_table(&self) -> [u32; 16] {
+        match *self {
+            Mode::Color => {
+                let d = COLORS;
+                fn c((r, g, b): (u8, u8, u8)) -> u32 {
+                    (r as u32) | ((g as u32) << 8) | ((b as u32) << 16)
+                }
+                [
+                    c(d[0x0]), c(d[0x1]), c(d[0x2]), c(d[0x3]),
+                    c(d[0x4]), c(d[0x5]), c(d[0x6]), c(d[0x7]),
+                    c(d[0x8]), c(d[0x9]), c(d[0xA]), c(d[0xB]),
+                    c(d[0xC]), c(d[0xD]), c(d[0xE]), c(d[0xF]),
+                ]
+            },
+            Mode::Grayscale => {
+                let d = GRAYSCALE;
+                fn c(x: u8) -> u32 {
+                    (x as u32) | ((x as u32) << 8) | ((x as u32) << 16)
+                }
+                [
+                    c(d[0x0]), c(d[0x1]), c(d[0x2]), c(d[0x3]),
+                    c(d[0x4]), c(d[0x5]), c(d[0x6]), c(d[0x7]),
+                    c(d[0x8]), c(d[0x9]), c(d[0xA]), c(d[0xB]),
+                    c(d[0xC]), c(d[0xD]), c(d[0xE]), c(d[0xF]),
+                ]
+            },
+            Mode::Monochrome => {
+                [
+                    0xFFFFFF, 0, 0, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 0, 0, 0, 0,
+                ]
+            },
+        }
+    }
+}
+/// Picks the character and colors for each pixel of an image with one pixel per cell, matching
+/// them in `space`. Along with the cells, returns how far the colors of each one were from its
+/// pixel before the difference was diffused onto its neighbours, in linear light.
+pub fn make_text<S: ColorSpace, R: Rng>(space: &S, img: Image, chars: &[(Vec<Char>, f32)], dither: bool, rng: &mut R) -> Result<(Vec<Cell>, Vec<f32>)> {
+    let (w, h) = (img.width, img.height);
+    let mut pixels = img.pixels;
+    pixels.resize((w * h + w + 1) as usize, Pixel::black());
+    let mut pixels: Vec<S::Color> = pixels.into_iter().map(|pixel| space.color(pixel)).collect();
+    let pairs = space.pairs();
+    let mut buf = Vec::with_capacity((w * h) as usize);
+    let mut error = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let index = y * w + x;
+            let pixel = pixels[index as usize];
+            let mut best_fg = 0;
+            let mut best_bg = 0;
+            let mut best_char: &[Char] = &[];
+            let mut best_color = pixel;
+            let mut best_diff = f32::INFINITY;
+            for &((c1, fg), (c2, bg)) in &pairs {
+                for &(ref ch, m) in chars {
+                    let combined = fg * m + bg * (1. - m);
+                    let d = space.distance(pixel, fg, bg, combined);
+                    if d < best_diff {
+                        best_fg = c1;
+                        best_bg = c2;
+                        best_char = &**ch;
+                        best_color = combined;
+                        best_diff = d;
+                    }
+                }
+            }
+            let char = rng.choose(best_char).ok_or(Error::NoGlyphs)?;
+            let attr = if char.invert {
+                (best_fg << 4) | best_bg
+            } else {
+                (best_bg << 4) | best_fg
+            };
+            buf.push(Cell { ch: char.ch, attr, transparent: false });
+            let err = pixel - best_color;
+            error.push(space.magnitude(err));
+            if dither {
+                pixels[(index + 1) as usize] += err * 0.4375;
+                pixels[(index + w - 1) as usize] += err * 0.1875;
+                pixels[(index + w) as usize] += err * 0.3125;
+                pixels[(index + w + 1) as usize] += err * 0.0625;
+            }
+        }
+    }
+    Ok((buf, error))
+}
+#[derive(Copy, Clone)]
+pub struct Char {
+    pub ch: u8,
+    pub invert: bool,
+}
+/// A character cell laid out like VGA text mode: an index into `CHARS` and an attribute byte
+/// with the foreground color in the low nibble and the background color in the high nibble.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Cell {
+    pub ch: u8,
+    pub attr: u8,
+    /// Whether the image barely covers this cell, so that backends which have a default
+    /// background can show that instead.
+    pub transparent: bool,
+}
+/// Groups the characters of the `w` by `h` font by how much of their cell they cover, loading
+/// its atlas from the current directory.
+pub fn calculate_chars(w: u32, h: u32) -> Result<Vec<(Vec<Char>, f32)>> {
+    let atlas = Atlas::load(w, h)?;
+    let total = (w * h) as usize;
+    let mult = 1. / (total as f32);
+    let mut res: Vec<Vec<Char>> = vec![Vec::new(); total + 1];
+    for i in 0..CHARS.len() as u32 {
+        let ch = i as u8;
+        let mut sum = 0;
+        for y in 0..h {
+            for x in 0..w {
+                if atlas.lit(ch, x, y) { sum += 1; }
+            }
+        }
+        res[sum].push(Char { ch, invert: false });
+        res[total - sum].push(Char { ch, invert: true });
+    }
+    Ok(res.into_iter().enumerate().filter(|(_, ch)| !ch.is_empty()).map(|(sum, ch)| (ch, (sum as f32) * mult)).collect())
+}
+/// Keeps only the characters from `calculate_chars` which are in `charset`, as indices into
+/// `CHARS`, dropping any coverage that is left without one.
+pub fn restrict_chars(chars: Vec<(Vec<Char>, f32)>, charset: &[u8]) -> Vec<(Vec<Char>, f32)> {
+    chars.into_iter().map(|(ch, m)| {
+        (ch.into_iter().filter(|c| charset.contains(&c.ch)).collect::<Vec<_>>(), m)
+    }).filter(|(ch, _)| !ch.is_empty()).collect()
+}
+/// Looks up each character of `s` in `CHARS`, failing with the first one that isn't there.
+pub fn charset(s: &str) -> ::std::result::Result<Vec<u8>, char> {
+    s.chars().map(|c| {
+        CHARS.iter().position(|&x| x as u32 == c as u32).map(|i| i as u8).ok_or(c)
+    }).collect()
+}
+/// How much each part of the difference between a pixel and a cell counts when picking the cell,
+/// where one is the usual balance. Monochrome has only the one part and ignores them.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Weights {
+    /// The blend of foreground and background that the character makes.
+    pub color: f32,
+    /// The foreground and background on their own, so that cells in colors close to the pixel
+    /// are favored over busier ones which only blend to it.
+    pub contrast: f32,
+}
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights { color: 1., contrast: 1. }
+    }
+}
+/// How images are turned into cells.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Settings {
+    pub mode: Mode,
+    /// The colors to pick from, laid out as in `Mode::color_table`. Monochrome ignores them.
+    pub palette: [u32; 16],
+    /// How cells are matched to pixels.
+    pub weights: Weights,
+    /// Whether the error of each cell is diffused onto the cells after it.
+    pub dither: bool,
+    /// Makes the choice between equally good characters the same every time.
+    pub seed: Option<u64>,
+    /// Cells where the image covers less than this are marked as transparent.
+    pub threshold: f32,
+}
+impl Settings {
+    /// Dithering with the palette of `mode`, and nothing made transparent.
+    pub fn new(mode: Mode) -> Settings {
+        Settings {
+            mode,
+            palette: mode.color_table(),
+            weights: Weights::default(),
+            dither: true,
+            seed: None,
+            threshold: 0.,
+        }
+    }
+}
+/// Converts an image with one pixel per cell into text.
+pub fn convert(settings: &Settings, img: Image, chars: &[(Vec<Char>, f32)]) -> Result<Vec<Cell>> {
+    convert_with_error(settings, img, chars).map(|(text, _)| text)
+}
+/// Like `convert`, but also returns the residual error of each cell as from `make_text`.
+pub fn convert_with_error(settings: &Settings, img: Image, chars: &[(Vec<Char>, f32)]) -> Result<(Vec<Cell>, Vec<f32>)> {
+    let &Settings { mode, ref palette, weights, dither, seed, threshold } = settings;
+    match mode {
+        Mode::Color => convert_in(&Rgb::new(palette, weights), dither, seed, threshold, img, chars),
+        Mode::Grayscale => convert_in(&Luminance::new(palette, weights), dither, seed, threshold, img, chars),
+        Mode::Monochrome => convert_in(&Monochrome, dither, seed, threshold, img, chars),
+    }
+}
+/// Like `convert_with_error`, but matching in `space`, which brings its own colors and weights,
+/// rather than in that of a mode. The rest are as in `Settings`.
+pub fn convert_in<S: ColorSpace>(space: &S, dither: bool, seed: Option<u64>, threshold: f32, img: Image, chars: &[(Vec<Char>, f32)]) -> Result<(Vec<Cell>, Vec<f32>)> {
+    let alpha = img.alpha.clone();
+    let (mut text, error) = make_text(space, img, chars, dither, &mut rng(seed)?)?;
+    for (cell, &a) in text.iter_mut().zip(&alpha) {
+        cell.transparent = a < threshold;
+    }
+    Ok((text, error))
+}
+// Where the choices between equally good characters come from
+fn rng(seed: Option<u64>) -> Result<StdRng> {
+    match seed {
+        Some(seed) => {
+            let mut bytes = [0; 32];
+            bytes[..8].copy_from_slice(&seed.to_le_bytes());
+            Ok(StdRng::from_seed(bytes))
+        },
+        None => StdRng::from_rng(thread_rng()).map_err(|e| {
+            Error::Backend(format!("couldn't seed the random number generator: {}", e))
+        }),
+    }
+}
+/// Works out how many columns and rows to use for an image so that it keeps its aspect ratio
+/// when every cell is `fw` by `fh` pixels, staying within whichever bounds are given.
+pub fn grid_size(img: &Image, (fw, fh): (u32, u32), cols: Option<u32>, rows: Option<u32>) -> Option<(u32, u32)> {
+    // How many rows each column is worth
+    let ratio = (img.height * fw) as f32 / (img.width * fh) as f32;
+    let by_cols = |c: u32| (c, ((c as f32 * ratio).round() as u32).max(1));
+    let by_rows = |r: u32| (((r as f32 / ratio).round() as u32).max(1), r);
+    match (cols, rows) {
+        (Some(c), Some(r)) => if c as f32 * ratio <= r as f32 { Some(by_cols(c)) } else { Some(by_rows(r)) },
+        (Some(c), None) => Some(by_cols(c)),
+        (None, Some(r)) => Some(by_rows(r)),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Blanks and half blocks which are as good as each other, so that the seed has a say
+    fn chars() -> Vec<(Vec<Char>, f32)> {
+        let char = |ch| Char { ch, invert: false };
+        vec![
+            (vec![char(0), char(32)], 0.),
+            (vec![char(176)], 0.25),
+            (vec![char(220), char(223)], 0.5),
+            (vec![char(219)], 1.),
+        ]
+    }
+    fn sample() -> Image {
+        Image::from_srgb(&[
+            (0x00, 0x00, 0x00), (0x80, 0x00, 0x00), (0xFF, 0xFF, 0xFF), (0x40, 0x40, 0x40),
+            (0x00, 0xFF, 0x00), (0x60, 0x60, 0x60), (0xC0, 0xC0, 0xC0), (0x14, 0xC8, 0x5A),
+        ], 4, 2)
+    }
+    fn seeded(mode: Mode) -> Vec<(u8, u8)> {
+        let settings = Settings { seed: Some(3), ..Settings::new(mode) };
+        let text = convert(&settings, sample(), &chars()).unwrap();
+        assert_eq!(text, convert(&settings, sample(), &chars()).unwrap());
+        text.iter().map(|cell| (cell.ch, cell.attr)).collect()
+    }
+
+    #[test]
+    fn seeded_color() {
+        assert_eq!(seeded(Mode::Color), [(0, 0), (32, 68), (0, 255), (176, 5), (0, 170), (223, 82), (0, 119), (220, 163)]);
+    }
+    #[test]
+    fn seeded_grayscale() {
+        assert_eq!(seeded(Mode::Grayscale), [(0, 0), (223, 67), (0, 255), (176, 67), (0, 221), (223, 101), (176, 188), (176, 171)]);
+    }
+    #[test]
+    fn seeded_monochrome() {
+        assert_eq!(seeded(Mode::Monochrome), [(0, 32), (32, 32), (219, 32), (0, 32), (219, 32), (0, 32), (223, 32), (220, 32)]);
+    }
+    // Linear red, green and blue matched on their own, from black, the primaries and white
+    struct Primaries(Vec<Channels<3>>);
+    impl ColorSpace for Primaries {
+        type Color = Channels<3>;
+        fn color(&self, pixel: Pixel) -> Channels<3> {
+            let (r, g, b) = pixel.to_srgb_f32();
+            Channels([r, g, b].map(srgb_to_linear))
+        }
+        fn pairs(&self) -> Vec<Pair<Channels<3>>> {
+            let colors = self.0.iter().enumerate().map(|(i, &c)| (i as u8, c));
+            colors.clone().flat_map(|fg| colors.clone().map(move |bg| (fg, bg))).collect()
+        }
+        fn distance(&self, pixel: Channels<3>, _: Channels<3>, _: Channels<3>, mixed: Channels<3>) -> f32 {
+            (pixel - mixed).length()
+        }
+        fn magnitude(&self, diff: Channels<3>) -> f32 {
+            diff.length()
+        }
+    }
+
+    #[test]
+    fn channels() {
+        let space = Primaries(vec![
+            Channels([0., 0., 0.]), Channels([1., 0., 0.]), Channels([0., 1., 0.]),
+            Channels([0., 0., 1.]), Channels([1., 1., 1.]),
+        ]);
+        let (text, error) = convert_in(&space, false, Some(3), 0., sample(), &chars()).unwrap();
+        let cells: Vec<_> = text.iter().map(|cell| (cell.ch, cell.attr)).collect();
+        assert_eq!(cells, [(0, 0), (176, 1), (0, 64), (32, 0), (0, 32), (32, 0), (220, 64), (220, 32)]);
+        // Black, white and green are in the palette
+        assert_eq!((error[0], error[2], error[4]), (0., 0., 0.));
+    }
+    // A white image of this size over a black background
+    fn white(w: u32, h: u32) -> Image {
+        Image::from_srgb(&vec![(255, 255, 255); (w * h) as usize], w, h)
+    }
+
+    #[test]
+    fn zoom_out() {
+        // Half size in the middle, with a pixel of background all around
+        let img = white(4, 4).zoom(0.5, (0, 0));
+        assert_eq!((img.width, img.height), (4, 4));
+        let inside = |i: usize| (1..3).contains(&(i % 4)) && (1..3).contains(&(i / 4));
+        for (i, &a) in img.alpha.iter().enumerate() {
+            assert_eq!(a, if inside(i) { 1. } else { 0. });
+        }
+        assert_eq!(img.pixels[0].to_srgb_f32(), (0., 0., 0.));
+        // Zooming far out leaves next to nothing, without making anything huge along the way
+        let img = white(1000, 1000).zoom(0.0001, (0, 0));
+        assert!(img.alpha.iter().all(|&a| a < 0.01));
+    }
+    #[test]
+    fn past_the_edges() {
+        let img = white(2, 2).crop(-2, -2, 3, 3).unwrap();
+        assert_eq!(img.alpha, [0., 0., 0., 0., 0., 0., 0., 0., 1.]);
+        let img = white(2, 2).crop(i32::MAX, i32::MIN, 2, 2).unwrap();
+        assert!(img.alpha.iter().all(|&a| a == 0.));
+        let img = white(2, 2).zoom(1., (i32::MAX, i32::MIN));
+        assert!(img.alpha.iter().all(|&a| a == 0.));
+    }
+    #[test]
+    fn huge_crops() {
+        assert!(white(2, 2).crop(0, 0, u32::MAX, u32::MAX).is_err());
+        // and ones whose far edge is past what an `i32` can hold
+        let img = white(2, 2).crop(i32::MAX - 1, 0, 3, 1).unwrap();
+        assert!(img.alpha.iter().all(|&a| a == 0.));
+    }
+}
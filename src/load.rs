@@ -0,0 +1,128 @@
+// Copyright © 2016, Peter Atashian
+
+use error::{Error, Result};
+#[cfg(feature = "exr")]
+use exr::prelude::{ReadChannels, ReadLayers, read};
+#[cfg(feature = "hdr")]
+use image::hdr::{HDRDecoder};
+use image::{ImageFormat, guess_format, load_from_memory_with_format};
+use pixel::{Pixel};
+use png::{BitDepth, ColorType, Decoder, Transformations};
+use std::fs::{File};
+#[cfg(feature = "exr")]
+use std::io::{Cursor};
+use std::io::{Read};
+use std::path::{Path};
+use Image;
+
+/// Loads an image from a file, compositing it over `background`. PNGs with sixteen bits per
+/// sample keep their full precision, but other formats such as PNM and TIFF are brought down to
+/// eight bits. Radiance HDR and OpenEXR images are left in linear light to be tone mapped.
+pub fn load(s: &str, background: Pixel) -> Result<Image> {
+    let mut data = Vec::new();
+    File::open(s)?.read_to_end(&mut data)?;
+    decode(&data, s, background)
+}
+/// Decodes an image which has already been read into memory, with `s` being where it came from.
+pub fn decode(data: &[u8], s: &str, background: Pixel) -> Result<Image> {
+    if let Some(img) = load_png16(data, background)? {
+        return Ok(img)
+    }
+    if data.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+        return load_exr(data, background)
+    }
+    let format = match guess_format(data) {
+        Ok(format) => format,
+        // Targa files have no signature, so all we can go by is the extension
+        Err(_) => match Path::new(s).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tga") => ImageFormat::TGA,
+            _ => return Err(Error::Unsupported("not a recognized image".to_string())),
+        },
+    };
+    let (name, feature, enabled) = match format {
+        ImageFormat::PNG => ("PNG", "", true),
+        ImageFormat::JPEG => ("JPEG", "jpeg", cfg!(feature = "jpeg")),
+        ImageFormat::GIF => ("GIF", "gif", cfg!(feature = "gif")),
+        ImageFormat::WEBP => ("WebP", "webp", cfg!(feature = "webp")),
+        ImageFormat::PNM => ("PNM", "pnm", cfg!(feature = "pnm")),
+        ImageFormat::TIFF => ("TIFF", "tiff", cfg!(feature = "tiff")),
+        ImageFormat::TGA => ("TGA", "tga", cfg!(feature = "tga")),
+        ImageFormat::BMP => ("BMP", "bmp", cfg!(feature = "bmp")),
+        ImageFormat::ICO => ("ICO", "ico", cfg!(feature = "ico")),
+        ImageFormat::HDR => ("Radiance HDR", "hdr", cfg!(feature = "hdr")),
+    };
+    if !enabled {
+        return Err(unsupported(name, feature))
+    }
+    if let ImageFormat::HDR = format {
+        return load_radiance(data, background)
+    }
+    let img = load_from_memory_with_format(data, format)?.to_rgba();
+    let data: Vec<_> = img.pixels().map(|pixel| {
+        (pixel.data[0], pixel.data[1], pixel.data[2], pixel.data[3])
+    }).collect();
+    Ok(Image::from_srgba(&data, img.width(), img.height(), background))
+}
+// For formats which this build was compiled without
+fn unsupported(name: &str, feature: &str) -> Error {
+    Error::Unsupported(format!("{} (rebuild with the {} feature)", name, feature))
+}
+// The image crate strips PNGs down to eight bits per sample, so those with sixteen are decoded
+// here instead. Anything else is left to the image crate.
+fn load_png16(data: &[u8], background: Pixel) -> Result<Option<Image>> {
+    let mut decoder = Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(_) => return Ok(None),
+    };
+    let (color, depth) = reader.output_color_type();
+    if depth != BitDepth::Sixteen {
+        return Ok(None)
+    }
+    let channels = match color {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => return Ok(None),
+    };
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| Error::Decode(e.to_string()))?;
+    let samples: Vec<f32> = buf.chunks(2).map(|b| {
+        (((b[0] as u16) << 8) | b[1] as u16) as f32 / 65535.
+    }).collect();
+    Ok(Some(Image::from_samples(&samples, channels, info.width, info.height, background)))
+}
+#[cfg(feature = "hdr")]
+fn load_radiance(data: &[u8], background: Pixel) -> Result<Image> {
+    let decoder = HDRDecoder::new(data)?;
+    let meta = decoder.metadata();
+    let samples: Vec<f32> = decoder.read_image_hdr()?.iter().flat_map(|p| {
+        p.data.to_vec()
+    }).collect();
+    Ok(Image::from_linear(&samples, 3, meta.width, meta.height, background))
+}
+#[cfg(not(feature = "hdr"))]
+fn load_radiance(_: &[u8], _: Pixel) -> Result<Image> {
+    Err(unsupported("Radiance HDR", "hdr"))
+}
+#[cfg(feature = "exr")]
+fn load_exr(data: &[u8], background: Pixel) -> Result<Image> {
+    let img = read().no_deep_data().largest_resolution_level().rgba_channels(
+        |size, _| (size.width(), vec![0.; size.width() * size.height() * 4]),
+        |&mut (w, ref mut buf): &mut (usize, Vec<f32>), pos, (r, g, b, a): (f32, f32, f32, f32)| {
+            let i = (pos.y() * w + pos.x()) * 4;
+            buf[i..i + 4].copy_from_slice(&[r, g, b, a]);
+        },
+    ).first_valid_layer().all_attributes().from_buffered(Cursor::new(data)).map_err(|e| {
+        Error::Decode(e.to_string())
+    })?;
+    let size = img.layer_data.size;
+    let samples = &img.layer_data.channel_data.pixels.1;
+    Ok(Image::from_linear(samples, 4, size.width() as u32, size.height() as u32, background))
+}
+#[cfg(not(feature = "exr"))]
+fn load_exr(_: &[u8], _: Pixel) -> Result<Image> {
+    Err(unsupported("OpenEXR", "exr"))
+}
@@ -1,397 +1,697 @@
 // Copyright © 2016, Peter Atashian
 
-extern crate image;
-extern crate rand;
+extern crate ascii;
+#[cfg(unix)]
+extern crate libc;
+extern crate serde;
+extern crate toml;
+#[cfg(windows)]
 extern crate wio;
 
-use image::{open};
-use pixel::{Pixel};
-use rand::{Rng, thread_rng};
+use ascii::{Animation, Atlas, Cell, Char, Error, Image, Mode, Result, Settings, ToneMap, VideoReader, Weights, calculate_chars, charset, convert_with_error, decode_animation, grid_size, load_animation, restrict_chars};
+use ascii::import::{Art, read_ansi, read_bin, read_xbin};
+use ascii::output::{Format, Style, write};
+use ascii::pixel::{Pixel};
+use ascii::quality::{measure, write_heatmap};
+use config::{Config, Preset};
 use std::env::{args};
-use wio::console::{CharInfo, Input, InputBuffer, ScreenBuffer};
+use std::fmt::{Display};
+use std::fs::{File, create_dir_all, read};
+use std::io::{BufWriter, IsTerminal, Read, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::process::{exit};
+use std::str::{FromStr};
 
-mod pixel;
+mod batch;
+mod config;
+#[cfg(windows)]
+mod console;
+#[cfg(unix)]
+mod terminal;
 
-const COLORS: &'static [(u8, u8, u8); 16] = &[
-    (0x00, 0x00, 0x00), (0x00, 0x00, 0x80), (0x00, 0x80, 0x00), (0x00, 0x80, 0x80),
-    (0x80, 0x00, 0x00), (0x80, 0x00, 0x80), (0x80, 0x80, 0x00), (0xC0, 0xC0, 0xC0),
-    (0x80, 0x80, 0x80), (0x00, 0x00, 0xFF), (0x00, 0xFF, 0x00), (0x00, 0xFF, 0xFF),
-    (0xFF, 0x00, 0x00), (0xFF, 0x00, 0xFF), (0xFF, 0xFF, 0x00), (0xFF, 0xFF, 0xFF),
-];
-const GRAYSCALE: &'static [u8; 16] = &[
-    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
-    0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
-];
+/// Scales the image to one pixel per character cell. `window` is the size of the window in
+/// cells, and is only needed when fitting to it.
+fn fit_image(img: Image, font: (u32, u32), opts: &Options, window: Option<(u32, u32)>) -> Image {
+    let (cols, rows) = match window {
+        Some((ww, wh)) if opts.fit => (opts.width.or(Some(ww)), opts.height.or(Some(wh))),
+        _ => (opts.width, opts.height),
+    };
+    match grid_size(&img, font, cols, rows) {
+        Some((w, h)) => img.resize(w, h),
+        None => {
+            let (fw, fh) = font;
+            let (w, h) = (img.width / fw + 1, img.height / fh + 1);
+            let img = img.increase_size(w * fw, h * fh);
+            img.shrink_factor(fw, fh)
+        },
+    }
+}
+/// Kinds of uncompressed video that can be streamed in on stdin.
+enum Video {
+    Y4m,
+    Rgb24(u32, u32),
+}
+// The font assumed when none is given, that of VGA text mode
+const DEFAULT_FONT: (u32, u32) = (8, 16);
+// The most pixels a crop can have, which at 16 bytes each is already 4 GiB
+const MAX_CROP: u64 = 1 << 28;
+const USAGE: &str = "\
+Usage: ascii [OPTIONS] [MODE] [FILE]
+
+Shows an image as colored text in the terminal, or saves it to a file. FILE may be `-` for stdin,
+which is also read when no file is given and something is piped in. ANSI, BIN and XBin art is
+shown or converted as it is.
+
+Defaults for the mode, palette, charset, font, dithering and weights, along with named presets of
+them, are read from ascii/config.toml in the user's configuration directory and then from the
+nearest ascii.toml in the current directory or above it. Options given here win over presets,
+which win over defaults:
+
+    [defaults]
+    font = \"8x16\"
+
+    [presets.print]
+    mode = \"grayscale\"
+    charset = \" .:-=+*#%@\"
+    dither = false
+    weights = { color = 1.0, contrast = 0.5 }
+
+Mode:
+    color, grayscale, monochrome
+    --mode MODE           Same as giving the mode on its own [default: color]
+
+Conversion:
+    --palette COLORS      Sixteen comma separated RRGGBB colors to convert with and show in
+    --charset CHARS       Only use these characters
+    --weights COLOR,CONTRAST
+                          How much the blended color of a cell and its foreground and background
+                          on their own count when matching it to a pixel [default: 1,1]
+    --no-dither           Don't diffuse the error of each cell onto the ones after it
+    --dither              Diffuse the error even if a preset or the defaults say not to
+    --seed N              Pick between equally good characters the same way every time
+    --font WxH            Size of the font in pixels, which needs a WxH.png atlas [default: 8x16]
+    --transparent AMOUNT  Leave cells covered less than this, from 0 to 1, showing through
+
+Configuration:
+    --preset NAME         Use a preset from the configuration files
+    --no-config           Ignore the configuration files
+
+Size:
+    --width COLUMNS       Fit the image within this many columns
+    --height ROWS         Fit the image within this many rows
+    --fit                 Fit the image to the terminal window
+    --crop X,Y,W,H        Only convert this part of the image
+    --zoom FACTOR         Magnify the image around its center
+    --pan X,Y             Move the center of the zoom by this many pixels
 
-const CHARS: &'static [u16; 256] = &[
-    0x0020, 0x263a, 0x263b, 0x2665, 0x2666, 0x2663, 0x2660, 0x2022,
-    0x25d8, 0x25cb, 0x25d9, 0x2642, 0x2640, 0x266a, 0x266b, 0x263c,
-    0x25ba, 0x25c4, 0x2195, 0x203c, 0x00b6, 0x00a7, 0x25ac, 0x21a8,
-    0x2191, 0x2193, 0x2192, 0x2190, 0x221f, 0x2194, 0x25b2, 0x25bc,
-    0x0020, 0x0021, 0x0022, 0x0023, 0x0024, 0x0025, 0x0026, 0x0027,
-    0x0028, 0x0029, 0x002a, 0x002b, 0x002c, 0x002d, 0x002e, 0x002f,
-    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
-    0x0038, 0x0039, 0x003a, 0x003b, 0x003c, 0x003d, 0x003e, 0x003f,
-    0x0040, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046, 0x0047,
-    0x0048, 0x0049, 0x004a, 0x004b, 0x004c, 0x004d, 0x004e, 0x004f,
-    0x0050, 0x0051, 0x0052, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057,
-    0x0058, 0x0059, 0x005a, 0x005b, 0x005c, 0x005d, 0x005e, 0x005f,
-    0x0060, 0x0061, 0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067,
-    0x0068, 0x0069, 0x006a, 0x006b, 0x006c, 0x006d, 0x006e, 0x006f,
-    0x0070, 0x0071, 0x0072, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077,
-    0x0078, 0x0079, 0x007a, 0x007b, 0x007c, 0x007d, 0x007e, 0x2302,
-    0x00c7, 0x00fc, 0x00e9, 0x00e2, 0x00e4, 0x00e0, 0x00e5, 0x00e7,
-    0x00ea, 0x00eb, 0x00e8, 0x00ef, 0x00ee, 0x00ec, 0x00c4, 0x00c5,
-    0x00c9, 0x00e6, 0x00c6, 0x00f4, 0x00f6, 0x00f2, 0x00fb, 0x00f9,
-    0x00ff, 0x00d6, 0x00dc, 0x00a2, 0x00a3, 0x00a5, 0x20a7, 0x0192,
-    0x00e1, 0x00ed, 0x00f3, 0x00fa, 0x00f1, 0x00d1, 0x00aa, 0x00ba,
-    0x00bf, 0x2310, 0x00ac, 0x00bd, 0x00bc, 0x00a1, 0x00ab, 0x00bb,
-    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x2561, 0x2562, 0x2556,
-    0x2555, 0x2563, 0x2551, 0x2557, 0x255d, 0x255c, 0x255b, 0x2510,
-    0x2514, 0x2534, 0x252c, 0x251c, 0x2500, 0x253c, 0x255e, 0x255f,
-    0x255a, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256c, 0x2567,
-    0x2568, 0x2564, 0x2565, 0x2559, 0x2558, 0x2552, 0x2553, 0x256b,
-    0x256a, 0x2518, 0x250c, 0x2588, 0x2584, 0x258c, 0x2590, 0x2580,
-    0x03b1, 0x00df, 0x0393, 0x03c0, 0x03a3, 0x03c3, 0x00b5, 0x03c4,
-    0x03a6, 0x0398, 0x03a9, 0x03b4, 0x221e, 0x03c6, 0x03b5, 0x2229,
-    0x2261, 0x00b1, 0x2265, 0x2264, 0x2320, 0x2321, 0x00f7, 0x2248,
-    0x00b0, 0x2219, 0x00b7, 0x221a, 0x207f, 0x00b2, 0x25a0, 0x00a0,
-];
+Color:
+    --background RRGGBB   Color to put behind transparent images [default: 000000]
+    --tonemap OPERATOR    Bring colors brighter than white into range with clamp, reinhard or
+                          aces [default: clamp]. Applies to every image, not only HDR ones
+    --exposure STOPS      Brighten or darken the image before tone mapping
 
-struct Image {
-    pixels: Vec<Pixel>,
-    width: u32,
-    height: u32,
+Output:
+    --output FILE         Save to a file instead of showing the image, or to stdout for `-`
+    --format FORMAT       ansi, text, html, svg, png, ans, xbin, bin or json [default: guessed
+                          from the output file, or else ansi]
+    --font-family NAME    Font to ask for in SVG output [default: monospace]
+    --cell WxH            Size of each cell in image output [default: the font size]
+    --title TEXT          Title to record in ANSI art, BIN and XBin output
+    --author NAME         Author to record in ANSI art, BIN and XBin output
+    --compress            Compress XBin output
+    --columns N           Width of ANSI art and BIN input
+    --quality             Print how faithful the conversion is to the image on stderr
+    --heatmap FILE        Save the error of each cell as a PNG
+
+Video:
+    --y4m                 Play YUV4MPEG2 video from stdin
+    --rgb24 WxH           Play raw 24-bit RGB frames of this size from stdin
+    --fps RATE            Frame rate to play video at
+
+Batch:
+    --batch DIR           Convert every image in a directory, saving each in the chosen format
+    --recursive           Include subdirectories
+    --include GLOB        Only convert files matching the pattern, which may be repeated
+    --out-dir DIR         Save into this directory rather than next to each image
+
+    -h, --help            Print this help
+    -V, --version         Print the version
+";
+struct Options {
+    mode: Mode,
+    palette: Option<[u32; 16]>,
+    charset: Option<Vec<u8>>,
+    weights: Weights,
+    dither: bool,
+    seed: Option<u64>,
+    filename: String,
+    video: Option<Video>,
+    fps: Option<f32>,
+    batch: Option<PathBuf>,
+    recursive: bool,
+    include: Vec<String>,
+    out_dir: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: Format,
+    font_family: String,
+    cell: Option<(u32, u32)>,
+    title: Option<String>,
+    author: String,
+    compress: bool,
+    columns: Option<u32>,
+    quality: bool,
+    heatmap: Option<PathBuf>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fit: bool,
+    font: Option<(u32, u32)>,
+    crop: Option<(i32, i32, u32, u32)>,
+    zoom: f32,
+    pan: (i32, i32),
+    background: Pixel,
+    transparent: f32,
+    tone_map: ToneMap,
+    exposure: f32,
 }
-impl Image {
-    fn from_srgb(img: &[(u8, u8, u8)], width: u32, height: u32) -> Image {
-        let pixels = img.iter().map(|p| {
-            Pixel::from_srgb(p.0, p.1, p.2)
-        }).collect();
-        Image {
-            pixels: pixels,
-            width: width,
-            height: height,
+impl Options {
+    /// Reads the options from the command line, exiting after printing the help or the version
+    /// if asked for them, or a usage error if they don't make sense.
+    fn parse() -> Options {
+        match Options::from_args(args().skip(1)) {
+            Ok(opts) => opts,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                eprintln!("Run `ascii --help` for usage.");
+                exit(2)
+            },
         }
     }
-    fn increase_size(&self, nw: u32, nh: u32) -> Image {
-        let mut buf = vec![Pixel::black(); (nw * nh) as usize];
-        for y in 0..self.height {
-            let orig = &self.pixels[(y * self.width) as usize..(y * self.width + self.width) as usize];
-            let line = &mut buf[(y * nw) as usize..(y * nw + self.width) as usize];
-            line.copy_from_slice(orig);
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> ::std::result::Result<Options, String> {
+        let mut filename = None;
+        let mut format = None;
+        // The settings which presets can also give, kept apart until they can be merged
+        let mut cli = Preset::default();
+        let mut preset = None;
+        let mut no_config = false;
+        // The first option given which only changes how files are written
+        let mut styling = None;
+        let mut opts = Options {
+            mode: Mode::Color,
+            palette: None,
+            charset: None,
+            weights: Weights::default(),
+            dither: true,
+            seed: None,
+            filename: String::new(),
+            video: None,
+            fps: None,
+            batch: None,
+            recursive: false,
+            include: Vec::new(),
+            out_dir: None,
+            output: None,
+            format: Format::Ansi,
+            font_family: "monospace".to_string(),
+            cell: None,
+            title: None,
+            author: String::new(),
+            compress: false,
+            columns: None,
+            quality: false,
+            heatmap: None,
+            width: None,
+            height: None,
+            fit: false,
+            font: None,
+            crop: None,
+            zoom: 1.,
+            pan: (0, 0),
+            background: Pixel::black(),
+            transparent: 0.,
+            tone_map: ToneMap::Clamp,
+            exposure: 0.,
+        };
+        while let Some(arg) = args.next() {
+            if ["--format", "--font-family", "--cell", "--title", "--author", "--compress"].contains(&&*arg) {
+                styling = styling.or_else(|| Some(arg.clone()));
+            }
+            match &*arg {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    exit(0)
+                },
+                "-V" | "--version" => {
+                    println!("ascii {}", env!("CARGO_PKG_VERSION"));
+                    exit(0)
+                },
+                "color" | "grayscale" | "monochrome" => cli.mode = Some(parse("mode", &arg)?),
+                "--mode" => cli.mode = Some(parse(&arg, &value(&mut args, &arg)?)?),
+                "--palette" => {
+                    let colors = value(&mut args, &arg)?;
+                    cli.palette = Some(palette(&arg, &colors.split(',').collect::<Vec<_>>())?);
+                },
+                "--charset" => cli.charset = Some(characters(&arg, &value(&mut args, &arg)?)?),
+                "--weights" => {
+                    let w: Vec<f32> = numbers(&arg, &value(&mut args, &arg)?, 2)?;
+                    cli.weights = Some(weights(&arg, Weights { color: w[0], contrast: w[1] })?);
+                },
+                "--no-dither" => cli.dither = Some(false),
+                "--dither" => cli.dither = Some(true),
+                "--preset" => preset = Some(value(&mut args, &arg)?),
+                "--no-config" => no_config = true,
+                "--seed" => opts.seed = Some(parse(&arg, &value(&mut args, &arg)?)?),
+                "--width" => opts.width = Some(positive(&arg, parse(&arg, &value(&mut args, &arg)?)?)?),
+                "--height" => opts.height = Some(positive(&arg, parse(&arg, &value(&mut args, &arg)?)?)?),
+                "--fit" => opts.fit = true,
+                "--font" => cli.font = Some(size(&arg, &value(&mut args, &arg)?)?),
+                "--crop" => {
+                    let rect: Vec<i32> = numbers(&arg, &value(&mut args, &arg)?, 4)?;
+                    let (w, h) = (positive(&arg, rect[2])?, positive(&arg, rect[3])?);
+                    if w as u64 * h as u64 > MAX_CROP {
+                        return Err(format!("--crop can be at most {} pixels, not {}x{}", MAX_CROP, w, h))
+                    }
+                    opts.crop = Some((rect[0], rect[1], w as u32, h as u32));
+                },
+                "--zoom" => opts.zoom = positive(&arg, finite(&arg, parse(&arg, &value(&mut args, &arg)?)?)?)?,
+                "--pan" => {
+                    let pan: Vec<i32> = numbers(&arg, &value(&mut args, &arg)?, 2)?;
+                    opts.pan = (pan[0], pan[1]);
+                },
+                "--background" => {
+                    let c = color(&arg, &value(&mut args, &arg)?)?;
+                    opts.background = Pixel::from_srgb((c >> 16) as u8, (c >> 8) as u8, c as u8);
+                },
+                "--transparent" => {
+                    let amount: f32 = parse(&arg, &value(&mut args, &arg)?)?;
+                    if !(0. ..=1.).contains(&amount) {
+                        return Err(format!("--transparent must be between 0 and 1, not {}", amount))
+                    }
+                    opts.transparent = amount;
+                },
+                "--tonemap" => opts.tone_map = parse(&arg, &value(&mut args, &arg)?)?,
+                "--exposure" => opts.exposure = finite(&arg, parse(&arg, &value(&mut args, &arg)?)?)?,
+                "--y4m" => opts.video = Some(Video::Y4m),
+                "--rgb24" => {
+                    let (w, h) = size(&arg, &value(&mut args, &arg)?)?;
+                    opts.video = Some(Video::Rgb24(w, h));
+                },
+                "--fps" => opts.fps = Some(positive(&arg, finite(&arg, parse(&arg, &value(&mut args, &arg)?)?)?)?),
+                "--batch" => opts.batch = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--recursive" => opts.recursive = true,
+                "--include" => opts.include.push(value(&mut args, &arg)?),
+                "--out-dir" => opts.out_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--output" => opts.output = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--format" => format = Some(parse(&arg, &value(&mut args, &arg)?)?),
+                "--font-family" => opts.font_family = value(&mut args, &arg)?,
+                "--cell" => opts.cell = Some(size(&arg, &value(&mut args, &arg)?)?),
+                "--title" => opts.title = Some(value(&mut args, &arg)?),
+                "--author" => opts.author = value(&mut args, &arg)?,
+                "--compress" => opts.compress = true,
+                "--columns" => opts.columns = Some(positive(&arg, parse(&arg, &value(&mut args, &arg)?)?)?),
+                "--quality" => opts.quality = true,
+                "--heatmap" => opts.heatmap = Some(PathBuf::from(value(&mut args, &arg)?)),
+                _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option `{}`", arg)),
+                _ if filename.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+                _ => filename = Some(arg),
+            }
         }
-        Image {
-            pixels: buf,
-            width: nw,
-            height: nh,
+        let config = if no_config { Config::default() } else { Config::load()? };
+        let settings = config.resolve(preset.as_deref())?.merge(cli);
+        opts.mode = settings.mode.unwrap_or(Mode::Color);
+        opts.palette = settings.palette;
+        opts.charset = settings.charset;
+        opts.font = settings.font;
+        opts.dither = settings.dither.unwrap_or(true);
+        opts.weights = settings.weights.unwrap_or_default();
+        // Without a format given, go by the extension of the output file
+        let guess = opts.output.as_ref().and_then(|path| path.extension()).and_then(|ext| {
+            Format::from_extension(&ext.to_string_lossy())
+        });
+        if let Some(format) = format.or(guess) {
+            opts.format = format;
         }
-    }
-    fn shrink_factor(&self, fw: u32, fh: u32) -> Image {
-        let mult = 1. / ((fw * fh) as f32);
-        let (nw, nh) = (self.width / fw, self.height / fh);
-        let mut buf = vec![Pixel::black(); (nw * nh) as usize];
-        for y in 0..nh {
-            for x in 0..nw {
-                let (bx, by) = (x * fw, y * fh);
-                let mut p = Pixel::black();
-                for yy in by..(by + fh) {
-                    for xx in bx..(bx + fw) {
-                        p = p + self.pixels[(yy * self.width + xx) as usize];
-                    }
-                }
-                buf[(y * nw + x) as usize] = p * mult;
+        if opts.batch.is_none() && (opts.recursive || !opts.include.is_empty() || opts.out_dir.is_some()) {
+            return Err("--recursive, --include and --out-dir only go with --batch".to_string())
+        }
+        if let Some(flag) = styling.filter(|_| opts.output.is_none() && opts.batch.is_none()) {
+            // Heatmaps are drawn with cells of the same size as PNG output
+            if flag != "--cell" || opts.heatmap.is_none() {
+                return Err(format!("{} only goes with --output or --batch", flag))
             }
         }
-        Image {
-            pixels: buf,
-            width: nw,
-            height: nh,
+        if opts.video.is_none() && opts.fps.is_some() {
+            return Err("--fps only goes with --y4m or --rgb24".to_string())
+        }
+        // Video is read from stdin and batches name a directory, so there is no file for either
+        if opts.video.is_some() || opts.batch.is_some() {
+            if opts.video.is_some() && opts.batch.is_some() {
+                return Err("video can't be played in a batch".to_string())
+            }
+            if let Some(filename) = filename {
+                return Err(format!("unexpected argument `{}`", filename))
+            }
+            if opts.quality || opts.heatmap.is_some() {
+                return Err("--quality and --heatmap only work on single images".to_string())
+            }
+            return Ok(opts)
+        }
+        // With nothing named, read the image from stdin if something is being piped in
+        if filename.is_none() && !stdin().is_terminal() {
+            filename = Some("-".to_string());
+        }
+        opts.filename = filename.ok_or("no image given")?;
+        Ok(opts)
+    }
+    /// How images should be turned into cells.
+    fn settings(&self) -> Settings {
+        Settings {
+            mode: self.mode,
+            palette: self.table(),
+            weights: self.weights,
+            dither: self.dither,
+            seed: self.seed,
+            threshold: self.transparent,
         }
     }
+    /// The colors to convert with and to show the cells in. Monochrome always uses its own.
+    fn table(&self) -> [u32; 16] {
+        match self.palette {
+            Some(palette) if self.mode != Mode::Monochrome => palette,
+            _ => self.mode.color_table(),
+        }
+    }
+    /// The characters of the `font` sized atlas to convert with, keeping only those in the
+    /// charset if there is one.
+    fn chars(&self, (w, h): (u32, u32)) -> Result<Vec<(Vec<Char>, f32)>> {
+        let chars = calculate_chars(w, h)?;
+        Ok(match self.charset {
+            Some(ref charset) => restrict_chars(chars, charset),
+            None => chars,
+        })
+    }
 }
-enum Mode {
-    Color,
-    Grayscale,
-    Monochrome,
+/// Converts an image for saving to a file, where there is no window to fit it to. Returns the
+/// cells along with how many there are to a row and the residual error of each one.
+fn render(img: Image, font: (u32, u32), chars: &[(Vec<Char>, f32)], opts: &Options) -> Result<(Vec<Cell>, u32, Vec<f32>)> {
+    let img = fit_image(img, font, opts, None);
+    let w = img.width;
+    let (text, error) = convert_with_error(&opts.settings(), img, chars)?;
+    Ok((text, w, error))
 }
-impl Mode {
-    fn color_table(&self) -> [u32; 16] {
-        match *self {
-            Mode::Color => {
-                let d = COLORS;
-                fn c((r, g, b): (u8, u8, u8)) -> u32 {
-                    (r as u32) | ((g as u32) << 8) | ((b as u32) << 16)
-                }
-                [
-                    c(d[0x0]), c(d[0x1]), c(d[0x2]), c(d[0x3]),
-                    c(d[0x4]), c(d[0x5]), c(d[0x6]), c(d[0x7]),
-                    c(d[0x8]), c(d[0x9]), c(d[0xA]), c(d[0xB]),
-                    c(d[0xC]), c(d[0xD]), c(d[0xE]), c(d[0xF]),
-                ]
-            },
-            Mode::Grayscale => {
-                let d = GRAYSCALE;
-                fn c(x: u8) -> u32 {
-                    (x as u32) | ((x as u32) << 8) | ((x as u32) << 16)
-                }
-                [
-                    c(d[0x0]), c(d[0x1]), c(d[0x2]), c(d[0x3]),
-                    c(d[0x4]), c(d[0x5]), c(d[0x6]), c(d[0x7]),
-                    c(d[0x8]), c(d[0x9]), c(d[0xA]), c(d[0xB]),
-                    c(d[0xC]), c(d[0xD]), c(d[0xE]), c(d[0xF]),
-                ]
-            },
-            Mode::Monochrome => {
-                [
-                    0xFFFFFF, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0,
-                ]
-            },
-        }
+/// How files written to `path` should look, according to the options.
+fn style(path: &Path, opts: &Options) -> Style {
+    Style {
+        table: opts.table(),
+        font_family: opts.font_family.clone(),
+        // Cells are as big as the font unless asked otherwise
+        cell: opts.cell.or(opts.font).unwrap_or(DEFAULT_FONT),
+        // Name the art after the file it goes in unless told otherwise
+        title: opts.title.clone().unwrap_or_else(|| {
+            path.file_stem().filter(|_| !is_stdout(path)).map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+        }),
+        author: opts.author.clone(),
+        compress: opts.compress,
+        atlas: None,
     }
 }
-fn load(s: &str) -> (u32, u32, Vec<(u8, u8, u8)>) {
-    let img = open(s).unwrap();
-    let img = img.to_rgba();
-    let data = img.pixels().map(|pixel| {
-        let (r, g, b, a) = (pixel.data[0] as u16, pixel.data[1] as u16, pixel.data[2] as u16, pixel.data[3] as u16);
-        ((r * a / 255) as u8, (g * a / 255) as u8, (b * a / 255) as u8)
-    }).collect();
-    (img.width(), img.height(), data)
+/// Whether output should go to stdout rather than to a file.
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
 }
-fn make_text(img: Image, chars: &[(Vec<Char>, f32)]) -> Vec<CharInfo> {
-    let mut rng = thread_rng();
-    let (w, h) = (img.width, img.height);
-    let mut pixels = img.pixels;
-    pixels.resize((w * h + w + 1) as usize, Pixel::black());
-    let colors: Vec<Pixel> = COLORS.iter().map(|&(r, g, b)| {
-        Pixel::from_srgb(r, g, b)
-    }).collect();
-    let mut buf = Vec::with_capacity((w * h) as usize);
-    for y in 0..h {
-        for x in 0..w {
-            let index = y * w + x;
-            let pixel = pixels[index as usize];
-            let mut best_fg = 0;
-            let mut best_bg = 0;
-            let mut best_char: &[Char] = &[];
-            let mut best_color = Pixel::black();
-            let mut best_diff = 100.;
-            for c1 in 0..16 {
-                for c2 in 0..16 {
-                    let fg = colors[c1];
-                    let bg = colors[c2];
-                    for &(ref ch, m) in chars {
-                        let combined = fg * m + bg * (1. - m);
-                        let d1 = pixel.lum_diff(fg);
-                        let d2 = pixel.lum_diff(bg);
-                        let dd = pixel.diff_sq(combined);
-                        let d = (d1 + d2) * 0.1 + dd;
-                        if d < best_diff {
-                            best_fg = c1;
-                            best_bg = c2;
-                            best_char = &**ch;
-                            best_color = combined;
-                            best_diff = d;
-                        }
-                    }
-                }
-            }
-            let char = rng.choose(best_char).unwrap();
-            let attr = if char.invert {
-                (best_fg << 4) | best_bg
-            } else {
-                (best_bg << 4) | best_fg
-            };
-            buf.push(CharInfo::new(char.ch, attr as u16));
-            let err = pixel - best_color;
-            pixels[(index + 1) as usize] += err * 0.4375;
-            pixels[(index + w - 1) as usize] += err * 0.1875;
-            pixels[(index + w) as usize] += err * 0.3125;
-            pixels[(index + w + 1) as usize] += err * 0.0625;
-        }
+/// Writes art out to a file in the chosen format, creating any missing directories, or to stdout
+/// for `-`. Any palette and font that came with the art are kept.
+fn save(path: &Path, art: Art, opts: &Options) -> Result<()> {
+    let mut style = style(path, opts);
+    if let Some(palette) = art.palette {
+        style.table = palette;
     }
-    buf
+    if let Some(font) = art.font {
+        style.cell = (font.width, font.height);
+        style.atlas = Some(font);
+    }
+    if is_stdout(path) {
+        let out = stdout();
+        let mut out = out.lock();
+        write(opts.format, &mut out, &art.cells, art.width, &style)?;
+        return Ok(out.flush()?)
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    write(opts.format, &mut file, &art.cells, art.width, &style)
 }
-fn grayscale_make_text(img: Image, chars: &[(Vec<Char>, f32)]) -> Vec<CharInfo> {
-    let mut rng = thread_rng();
-    let (w, h) = (img.width, img.height);
-    let mut pixels = img.pixels;
-    pixels.resize((w * h + w + 1) as usize, Pixel::black());
-    let mut pixels: Vec<f32> = pixels.iter().map(|pixel| {
-        pixel.luminosity()
-    }).collect();
-    let colors: Vec<f32> = GRAYSCALE.iter().map(|&x| {
-        Pixel::from_srgb(x, x, x).luminosity()
-    }).collect();
-    let mut buf = Vec::with_capacity((w * h) as usize);
-    for y in 0..h {
-        for x in 0..w {
-            let index = y * w + x;
-            let pixel = pixels[index as usize];
-            let mut best_fg = 0;
-            let mut best_bg = 0;
-            let mut best_char: &[Char] = &[];
-            let mut best_color = 0.;
-            let mut best_diff = 100.;
-            for c1 in 0..colors.len() {
-                for c2 in 0..colors.len() {
-                    let fg = colors[c1];
-                    let bg = colors[c2];
-                    for &(ref ch, m) in chars {
-                        let combined = fg * m + bg * (1. - m);
-                        let d1 = (pixel - fg).abs();
-                        let d2 = (pixel - bg).abs();
-                        let dd = (pixel - combined).abs();
-                        let d = (d1 + d2) * 0.05 + dd;
-                        if d < best_diff {
-                            best_fg = c1;
-                            best_bg = c2;
-                            best_char = &**ch;
-                            best_color = combined;
-                            best_diff = d;
-                        }
-                    }
-                }
-            }
-            let char = rng.choose(best_char).unwrap();
-            let attr = if char.invert {
-                (best_fg << 4) | best_bg
-            } else {
-                (best_bg << 4) | best_fg
-            };
-            buf.push(CharInfo::new(char.ch, attr as u16));
-            let err = pixel - best_color;
-            pixels[(index + 1) as usize] += err * 0.4375;
-            pixels[(index + w - 1) as usize] += err * 0.1875;
-            pixels[(index + w) as usize] += err * 0.3125;
-            pixels[(index + w + 1) as usize] += err * 0.0625;
-        }
+/// Writes the residual error of each cell out as a heatmap, with the cells as big as in PNG output.
+fn save_heatmap(path: &Path, error: &[f32], w: u32, opts: &Options) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
     }
-    buf
+    let mut file = BufWriter::new(File::create(path)?);
+    write_heatmap(&mut file, error, w, opts.cell.or(opts.font).unwrap_or(DEFAULT_FONT))
 }
-fn monochrome_make_text(img: Image, chars: &[(Vec<Char>, f32)]) -> Vec<CharInfo> {
-    let mut rng = thread_rng();
-    let (w, h) = (img.width, img.height);
-    let mut pixels = img.pixels;
-    pixels.resize((w * h + w + 1) as usize, Pixel::black());
-    let mut pixels: Vec<f32> = pixels.iter().map(|pixel| {
-        pixel.luminosity()
-    }).collect();
-    let mut buf = Vec::with_capacity((w * h) as usize);
-    for y in 0..h {
-        for x in 0..w {
-            let index = y * w + x;
-            let pixel = pixels[index as usize];
-            let mut best_char: &[Char] = &[];
-            let mut best_diff = 100.;
-            let mut best_color = 0.;
-            for &(ref ch, color) in chars {
-                let diff = (pixel - color).abs();
-                if diff < best_diff {
-                    best_diff = diff;
-                    best_color = color;
-                    best_char = &**ch;
-                }
-            }
-            let char = rng.choose(best_char).unwrap();
-            buf.push(CharInfo::new(char.ch, if char.invert { 0x02 } else { 0x20 }));
-            let err = pixel - best_color;
-            pixels[(index + 1) as usize] += err * 0.4375;
-            pixels[(index + w - 1) as usize] += err * 0.1875;
-            pixels[(index + w) as usize] += err * 0.3125;
-            pixels[(index + w + 1) as usize] += err * 0.0625;
-        }
+/// Reads text mode art, going by the extension. Returns nothing for anything else.
+fn read_art(path: &Path, opts: &Options) -> Option<Result<Art>> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    let data = match &*ext {
+        "ans" | "bin" | "xb" | "xbin" => read(path).map_err(Error::from),
+        _ => return None,
+    };
+    Some(data.and_then(|data| match &*ext {
+        "ans" => Ok(read_ansi(&data, opts.columns)),
+        "bin" => Ok(read_bin(&data, opts.columns)),
+        _ => read_xbin(&data),
+    }))
+}
+// The value which has to come after `flag`
+fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> ::std::result::Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+fn parse<T: FromStr>(flag: &str, s: &str) -> ::std::result::Result<T, String> where T::Err: Display {
+    s.parse().map_err(|e| format!("invalid value `{}` for {}: {}", s, flag, e))
+}
+fn positive<T: Default + Display + PartialOrd>(flag: &str, x: T) -> ::std::result::Result<T, String> {
+    if x > T::default() { Ok(x) } else { Err(format!("{} must be more than zero, not {}", flag, x)) }
+}
+// Infinity and NaN parse as numbers, but nothing good comes of them
+fn finite(flag: &str, x: f32) -> ::std::result::Result<f32, String> {
+    if x.is_finite() { Ok(x) } else { Err(format!("{} must be a finite number, not {}", flag, x)) }
+}
+fn weights(flag: &str, weights: Weights) -> ::std::result::Result<Weights, String> {
+    let (color, contrast) = (finite(flag, weights.color)?, finite(flag, weights.contrast)?);
+    if color < 0. || contrast < 0. {
+        return Err(format!("{} can't be negative, not {},{}", flag, color, contrast))
     }
-    buf
+    Ok(weights)
 }
-#[derive(Copy, Clone)]
-struct Char {
-    ch: u16,
-    invert: bool,
+// Sizes are written as `WxH`
+fn size(flag: &str, s: &str) -> ::std::result::Result<(u32, u32), String> {
+    let size: Vec<u32> = numbers_by(flag, s, 'x', 2)?;
+    Ok((positive(flag, size[0])?, positive(flag, size[1])?))
 }
-fn calculate_chars(w: u32, h: u32) -> Vec<(Vec<Char>, f32)> {
-    let name = format!("{}x{}.png", w, h);
-    let img = open(&name).unwrap().to_rgba();
-    let total = (w * h) as usize;
-    let mult = 1. / (total as f32);
-    let mut res: Vec<Vec<Char>> = vec![Vec::new(); total + 1];
-    for (i, &ch) in CHARS.iter().enumerate() {
-        let i = i as u32;
-        let mut sum = 0;
-        let (bx, by) = (i % 16 * w, i / 16 * h);
-        for y in by..(by + h) {
-            for x in bx..(bx + w) {
-                let pix = img.get_pixel(x, y);
-                if pix.data[0] != 0 { sum += 1; }
-            }
+// Exactly `count` numbers separated by commas
+fn numbers<T: FromStr>(flag: &str, s: &str, count: usize) -> ::std::result::Result<Vec<T>, String> where T::Err: Display {
+    numbers_by(flag, s, ',', count)
+}
+fn numbers_by<T: FromStr>(flag: &str, s: &str, sep: char, count: usize) -> ::std::result::Result<Vec<T>, String> where T::Err: Display {
+    let numbers = s.split(sep).map(|x| parse(flag, x)).collect::<::std::result::Result<Vec<T>, _>>()?;
+    if numbers.len() != count {
+        return Err(format!("{} needs {} numbers separated by `{}`, not `{}`", flag, count, sep, s))
+    }
+    Ok(numbers)
+}
+// Palettes are sixteen colors laid out as in `Mode::color_table`
+fn palette(flag: &str, colors: &[&str]) -> ::std::result::Result<[u32; 16], String> {
+    if colors.len() != 16 {
+        return Err(format!("{} needs 16 colors, not {}", flag, colors.len()))
+    }
+    let mut palette = [0; 16];
+    for (entry, c) in palette.iter_mut().zip(colors) {
+        let c = color(flag, c)?;
+        // The table has red in the low byte
+        *entry = (c >> 16) | (c & 0xFF00) | ((c & 0xFF) << 16);
+    }
+    Ok(palette)
+}
+// Characters are looked up in the font's code page
+fn characters(flag: &str, s: &str) -> ::std::result::Result<Vec<u8>, String> {
+    if s.is_empty() {
+        return Err(format!("{} needs at least one character", flag))
+    }
+    charset(s).map_err(|c| format!("{} has `{}`, which isn't in the font", flag, c))
+}
+// Colors are written in hex as `RRGGBB`, maybe after a `#`
+fn color(flag: &str, s: &str) -> ::std::result::Result<u32, String> {
+    let hex = s.trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(c) if hex.len() == 6 => Ok(c),
+        _ => Err(format!("invalid color `{}` for {}: expected RRGGBB", s, flag)),
+    }
+}
+/// Applies the tone mapping, crop and zoom options to a freshly loaded image.
+fn prepare(mut img: Image, opts: &Options) -> Result<Image> {
+    img.tone_map(opts.tone_map, opts.exposure);
+    if let Some((x, y, w, h)) = opts.crop {
+        img = img.crop(x, y, w, h)?;
+    }
+    if opts.zoom != 1. || opts.pan != (0, 0) {
+        img = img.zoom(opts.zoom, opts.pan);
+    }
+    Ok(img)
+}
+/// Prints what went wrong and exits. Output going to a pipe that was closed early, such as into
+/// `head`, isn't an error and just ends the program.
+fn fail(context: &str, e: Error) -> ! {
+    if e.is_broken_pipe() {
+        exit(0)
+    }
+    eprintln!("{}: {}", context, e);
+    exit(1)
+}
+/// Saves the art if there is somewhere to save it, and otherwise shows it.
+fn show_art(art: Art, opts: &Options) {
+    if let Some(ref path) = opts.output {
+        if let Err(e) = save(path, art, opts) {
+            fail(&format!("Failed to save {}", path.display()), e)
         }
-        res[sum].push(Char { ch: ch, invert: false });
-        res[total - sum].push(Char { ch: ch, invert: true });
+        return
+    }
+    let table = art.palette.unwrap_or_else(|| opts.table());
+    #[cfg(windows)]
+    let result = console::show_text(&art.cells, art.width, &table, opts);
+    #[cfg(unix)]
+    let result = terminal::show_text(&art.cells, art.width, &table);
+    if let Err(e) = result {
+        fail("Failed to show the image", e)
+    }
+}
+/// Prints how faithful the cells are to the image they were made from, drawing them with the
+/// same glyphs that were used to pick the characters.
+fn report(source: &Image, text: &[Cell], w: u32, font: (u32, u32), opts: &Options) {
+    let atlas = match Atlas::load(font.0, font.1) {
+        Ok(atlas) => atlas,
+        Err(e) => fail("Failed to measure the quality", e),
+    };
+    // Without a size to scale to, the image was padded out to whole cells, as in `fit_image`
+    let area = match grid_size(source, font, opts.width, opts.height) {
+        Some(_) => (w * font.0, text.len() as u32 / w.max(1) * font.1),
+        None => (source.width, source.height),
+    };
+    let quality = measure(source, area, text, w, &atlas, &opts.table());
+    // Kept off stdout, where the art itself may be going
+    eprintln!("MSE  {:.6}", quality.mse);
+    eprintln!("PSNR {:.2} dB", quality.psnr);
+    eprintln!("SSIM {:.4}", quality.ssim);
+    eprintln!("ΔE   {:.2}", quality.delta_e);
+}
+/// Loads the image named on the command line, with `-` meaning stdin.
+fn open(opts: &Options) -> Result<Animation> {
+    if opts.filename != "-" {
+        return load_animation(&opts.filename, opts.background)
+    }
+    let mut data = Vec::new();
+    stdin().read_to_end(&mut data)?;
+    decode_animation(&data, "-", opts.background)
+}
+/// Plays uncompressed video from stdin until it runs out.
+fn stream(video: &Video, opts: &Options) {
+    let input = stdin();
+    let input = input.lock();
+    let reader = match *video {
+        Video::Y4m => VideoReader::y4m(input),
+        Video::Rgb24(w, h) => VideoReader::rgb24(input, w, h, 25.),
+    };
+    let mut reader = match reader {
+        Ok(reader) => reader,
+        Err(e) => fail("Failed to read video", e),
+    };
+    if let Some(fps) = opts.fps {
+        reader = reader.with_fps(fps);
+    }
+    // A bad frame ends playback, and is reported once the screen has been put back
+    let mut error = None;
+    let frames = reader.map_while(|frame| match frame.and_then(|mut frame| {
+        frame.image = prepare(frame.image, opts)?;
+        Ok(frame)
+    }) {
+        Ok(frame) => Some(frame),
+        Err(e) => {
+            error = Some(e);
+            None
+        },
+    });
+    #[cfg(windows)]
+    let result = console::stream(frames, opts);
+    #[cfg(unix)]
+    let result = terminal::stream(frames, opts);
+    if let Err(e) = result {
+        fail("Failed to play video", e)
+    }
+    if let Some(e) = error {
+        fail("Failed to read video", e)
     }
-    res.into_iter().enumerate().filter(|&(_, ref ch)| ch.len() != 0).map(|(sum, ch)| (ch, (sum as f32) * mult)).collect()
 }
 fn main() {
+    let opts = Options::parse();
+    if let Some(ref video) = opts.video {
+        return stream(video, &opts)
+    }
+    if let Some(ref dir) = opts.batch {
+        if batch::run(dir, &opts) > 0 {
+            exit(1)
+        }
+        return
+    }
+    // Text mode art is shown as it is rather than being converted again
+    match read_art(Path::new(&opts.filename), &opts) {
+        Some(Ok(art)) => return show_art(art, &opts),
+        Some(Err(e)) => fail(&format!("Failed to load {}", opts.filename), e),
+        None => (),
+    }
     // Load image from file
-    let args: Vec<_> = args().collect();
-    let mut filename = args.get(2).map(|x| &**x);
-    let mode = match &*args[1] {
-        "color" => Mode::Color,
-        "grayscale" => Mode::Grayscale,
-        "monochrome" => Mode::Monochrome,
-        file => {
-            filename = Some(file);
-            Mode::Color
+    let mut anim = match open(&opts) {
+        Ok(anim) => anim,
+        Err(e) => {
+            let name = if opts.filename == "-" { "stdin" } else { &opts.filename };
+            fail(&format!("Failed to load {}", name), e)
         },
     };
-    let (width, height, srgb) = load(filename.unwrap());
-    let img = Image::from_srgb(&srgb, width, height);
-    // Back up console colors
-    let orig = ScreenBuffer::from_conout().unwrap();
-    let orig_info = orig.info_ex().unwrap();
-    // Create a new console buffer
-    let cout = ScreenBuffer::new().unwrap();
-    // Calculate some dimensions
-    let (fw, fh) = cout.font_size().unwrap();
-    let (fw, fh) = (fw as u32, fh as u32);
-    let (w, h) = (img.width / fw + 1, img.height / fh + 1);
-    // Figure out characters
-    let chars = calculate_chars(fw, fh);
-    // Setup the console buffer info
-    let mut info = cout.info_ex().unwrap();
-    {
-        let rinfo = info.raw_mut();
-        rinfo.ColorTable = mode.color_table();
-        rinfo.dwSize.X = w as i16;
-        rinfo.dwSize.Y = h as i16;
-        rinfo.srWindow.Right = w as i16;
-        rinfo.srWindow.Bottom = h as i16;
-        rinfo.dwMaximumWindowSize.X = w as i16;
-        rinfo.dwMaximumWindowSize.Y = h as i16;
-    }
-    cout.set_active().unwrap();
-    cout.set_info_ex(info).unwrap();
-    // Resize image
-    let img = img.increase_size(w * fw, h * fh);
-    let img = img.shrink_factor(fw, fh);
-    // Display image
-    let text = match mode {
-        Mode::Color => make_text(img, &chars),
-        Mode::Grayscale => grayscale_make_text(img, &chars),
-        Mode::Monochrome => monochrome_make_text(img, &chars),
-        //_ => unreachable!(),
+    let frames = anim.frames.into_iter().map(|mut frame| {
+        frame.image = prepare(frame.image, &opts)?;
+        Ok(frame)
+    }).collect();
+    anim.frames = match frames {
+        Ok(frames) => frames,
+        Err(e) => fail("Failed to convert the image", e),
     };
-    cout.write_output(&text, (w as i16, h as i16), (0, 0)).unwrap();
-    // Wait for keyboard input
-    let cin = InputBuffer::from_conin().unwrap();
-    cin.flush_input().unwrap();
-    'done: loop {
-        for input in cin.read_input().unwrap() {
-            if let Input::Key{key_code: 0x0D, ..} = input { break 'done }
+    if opts.output.is_some() || opts.quality || opts.heatmap.is_some() {
+        // Only the first frame of an animation makes it into the files or the report
+        let font = opts.font.unwrap_or(DEFAULT_FONT);
+        let img = anim.frames.swap_remove(0).image;
+        let source = if opts.quality { Some(img.clone()) } else { None };
+        let (cells, width, error) = match opts.chars(font).and_then(|chars| {
+            render(img, font, &chars, &opts)
+        }) {
+            Ok(result) => result,
+            Err(e) => fail("Failed to convert the image", e),
+        };
+        if let Some(source) = source {
+            report(&source, &cells, width, font, &opts);
+        }
+        if let Some(ref path) = opts.heatmap {
+            if let Err(e) = save_heatmap(path, &error, width, &opts) {
+                fail(&format!("Failed to save {}", path.display()), e)
+            }
+        }
+        if opts.output.is_some() {
+            show_art(Art { cells, width, palette: None, font: None }, &opts);
         }
+        return
+    }
+    #[cfg(windows)]
+    let result = console::display(anim, &opts);
+    #[cfg(unix)]
+    let result = terminal::display(anim, &opts);
+    if let Err(e) = result {
+        fail("Failed to show the image", e)
     }
-    // Restore console colors
-    orig.set_info_ex(orig_info).unwrap();
-    orig.set_active().unwrap();
 }
@@ -0,0 +1,581 @@
+// Copyright © 2016, Peter Atashian
+
+use png::{BitDepth, ColorType, Encoder};
+use sauce::{DataType, Sauce, swap_red_blue};
+use serde::{Deserialize, Serialize};
+use serde_json::{to_writer};
+use std::borrow::{Cow};
+use std::char::{from_u32};
+use std::io::{Write};
+use std::str::{FromStr};
+use {Atlas, CHARS, COLORS, Cell, Error, Result};
+
+/// File formats that converted images can be saved as.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Text with 24-bit color escape codes, as shown in a terminal.
+    Ansi,
+    /// Plain UTF-8 text without any color.
+    Text,
+    /// A `<pre>` block of spans colored with inline styles.
+    Html,
+    /// Scalable vector graphics, with text over rectangles for the backgrounds.
+    Svg,
+    /// A picture of the text, drawn with the same glyphs used to pick the characters.
+    Png,
+    /// Classic ANSI art in code page 437 with a SAUCE record, for ANSI art viewers.
+    AnsiArt,
+    /// Character and attribute pairs along with the palette and font they were made for.
+    XBin,
+    /// Raw character and attribute pairs, as in VGA text mode memory.
+    Bin,
+    /// A `Grid` as JSON, for programs which draw the cells themselves.
+    Json,
+}
+impl Format {
+    /// The extension to give files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Ansi => "ansi",
+            Format::Text => "txt",
+            Format::Html => "html",
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::AnsiArt => "ans",
+            Format::XBin => "xb",
+            Format::Bin => "bin",
+            Format::Json => "json",
+        }
+    }
+    /// Guesses the format from a file extension.
+    pub fn from_extension(ext: &str) -> Option<Format> {
+        match &*ext.to_ascii_lowercase() {
+            "ansi" => Some(Format::Ansi),
+            "txt" => Some(Format::Text),
+            "htm" | "html" => Some(Format::Html),
+            "svg" => Some(Format::Svg),
+            "png" => Some(Format::Png),
+            "ans" => Some(Format::AnsiArt),
+            "xb" | "xbin" => Some(Format::XBin),
+            "bin" => Some(Format::Bin),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Format, String> {
+        match s {
+            "ansi" => Ok(Format::Ansi),
+            "text" => Ok(Format::Text),
+            "html" => Ok(Format::Html),
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            "ans" => Ok(Format::AnsiArt),
+            "xbin" => Ok(Format::XBin),
+            "bin" => Ok(Format::Bin),
+            "json" => Ok(Format::Json),
+            _ => Err("expected ansi, text, html, svg, png, ans, xbin, bin or json".to_string()),
+        }
+    }
+}
+/// How the cells should look, for the formats which get a say in it.
+pub struct Style {
+    /// The palette, as returned by `Mode::color_table`.
+    pub table: [u32; 16],
+    /// Which font to ask for in SVG output.
+    pub font_family: String,
+    /// The size of each cell in pixels. PNG output needs an atlas of this size.
+    pub cell: (u32, u32),
+    /// Recorded in the formats which have room for them.
+    pub title: String,
+    pub author: String,
+    /// Whether to compress the formats which support it.
+    pub compress: bool,
+    /// The glyphs to draw PNG output with and to store in XBin output. When not given, the atlas
+    /// for the cell size is loaded.
+    pub atlas: Option<Atlas>,
+}
+/// Writes out the cells, `w` to a row, in the given format.
+pub fn write<W: Write>(format: Format, out: &mut W, text: &[Cell], w: u32, style: &Style) -> Result<()> {
+    match format {
+        Format::Ansi => write_ansi(out, text, w, &style.table),
+        Format::Text => write_text(out, text, w),
+        Format::Html => write_html(out, text, w, &style.table),
+        Format::Svg => write_svg(out, text, w, style),
+        Format::Png => write_png(out, text, w, &*load_atlas(style)?, &style.table),
+        Format::AnsiArt => write_ansi_art(out, text, w, style),
+        Format::XBin => write_xbin(out, text, w, &*load_atlas(style)?, style),
+        Format::Bin => write_bin(out, text, w, style),
+        Format::Json => write_json(out, text, w, &style.table),
+    }
+}
+/// Writes out just the characters, one line per row. Transparent cells become spaces, and
+/// trailing spaces are left off.
+pub fn write_text<W: Write>(out: &mut W, text: &[Cell], w: u32) -> Result<()> {
+    for row in text.chunks(w as usize) {
+        let line: String = row.iter().map(glyph).collect();
+        writeln!(out, "{}", line.trim_end_matches(' '))?;
+    }
+    Ok(())
+}
+// The atlas given in the style, or else the one for its cell size
+fn load_atlas(style: &Style) -> Result<Cow<'_, Atlas>> {
+    if let Some(ref atlas) = style.atlas {
+        return Ok(Cow::Borrowed(atlas))
+    }
+    Atlas::load(style.cell.0, style.cell.1).map(Cow::Owned)
+}
+// Splits a color table entry into its red, green and blue parts
+fn rgb(c: u32) -> (u32, u32, u32) {
+    (c & 0xFF, (c >> 8) & 0xFF, (c >> 16) & 0xFF)
+}
+fn hex(c: u32) -> String {
+    let (r, g, b) = rgb(c);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+// The character to show for a cell, with transparent ones left blank
+fn glyph(cell: &Cell) -> char {
+    if cell.transparent { ' ' } else { from_u32(CHARS[cell.ch as usize] as u32).unwrap() }
+}
+// Escapes the characters which are special in HTML and XML
+fn escape(ch: char) -> String {
+    match ch {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        ch => ch.to_string(),
+    }
+}
+// Splits a row into runs of cells for which `key` is the same
+fn runs<K: PartialEq, F: Fn(&Cell) -> K>(row: &[Cell], key: F) -> Vec<(usize, &[Cell])> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < row.len() {
+        let run = row[start..].iter().take_while(|cell| key(cell) == key(&row[start])).count();
+        runs.push((start, &row[start..start + run]));
+        start += run;
+    }
+    runs
+}
+/// Writes out the cells using 24-bit color escape codes, with the colors taken from the same
+/// table that would be loaded into the console.
+pub fn write_ansi<W: Write>(out: &mut W, text: &[Cell], w: u32, table: &[u32; 16]) -> Result<()> {
+    for row in text.chunks(w as usize) {
+        let mut last = None;
+        for cell in row {
+            // Transparent cells are left blank on the default background
+            let attr = if cell.transparent { None } else { Some(cell.attr) };
+            if last != Some(attr) {
+                match attr {
+                    Some(attr) => {
+                        let fg = rgb(table[(attr & 0xF) as usize]);
+                        let bg = rgb(table[(attr >> 4) as usize]);
+                        write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m", fg.0, fg.1, fg.2, bg.0, bg.1, bg.2)?;
+                    },
+                    None => write!(out, "\x1b[0m")?,
+                }
+                last = Some(attr);
+            }
+            write!(out, "{}", glyph(cell))?;
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
+/// Writes out the cells as an HTML `<pre>` block, with each run of cells sharing the same colors
+/// merged into a single span. Transparent cells are left unstyled.
+pub fn write_html<W: Write>(out: &mut W, text: &[Cell], w: u32, table: &[u32; 16]) -> Result<()> {
+    writeln!(out, "<pre style=\"font-family: monospace; line-height: 1\">")?;
+    for row in text.chunks(w as usize) {
+        for (_, run) in runs(row, |cell| if cell.transparent { None } else { Some(cell.attr) }) {
+            let chars: String = run.iter().map(|cell| escape(glyph(cell))).collect();
+            if run[0].transparent {
+                write!(out, "{}", chars)?;
+            } else {
+                let attr = run[0].attr;
+                let (fg, bg) = (hex(table[(attr & 0xF) as usize]), hex(table[(attr >> 4) as usize]));
+                write!(out, "<span style=\"color: {}; background: {}\">{}</span>", fg, bg, chars)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "</pre>")?;
+    Ok(())
+}
+/// Writes out the cells as an SVG image, with a rectangle for each run of background color and a
+/// line of text for each row. Every character is placed at its own cell so the grid lines up
+/// whatever font ends up being used.
+pub fn write_svg<W: Write>(out: &mut W, text: &[Cell], w: u32, style: &Style) -> Result<()> {
+    let (cw, ch) = style.cell;
+    let h = text.len() as u32 / w.max(1);
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", w * cw, h * ch)?;
+    writeln!(out, "<g shape-rendering=\"crispEdges\">")?;
+    for (y, row) in text.chunks(w as usize).enumerate() {
+        for (x, run) in runs(row, |cell| if cell.transparent { None } else { Some(cell.attr >> 4) }) {
+            if run[0].transparent {
+                continue
+            }
+            let fill = hex(style.table[(run[0].attr >> 4) as usize]);
+            writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>", x as u32 * cw, y as u32 * ch, run.len() as u32 * cw, ch, fill)?;
+        }
+    }
+    writeln!(out, "</g>")?;
+    let family = style.font_family.chars().map(escape).collect::<String>().replace('"', "&quot;");
+    writeln!(out, "<g font-family=\"{}\" font-size=\"{}\" xml:space=\"preserve\">", family, ch)?;
+    for (y, row) in text.chunks(w as usize).enumerate() {
+        // Put the baseline about where it sits in most fonts
+        write!(out, "<text y=\"{}\">", (y as u32 + 1) * ch - ch / 5)?;
+        for (x, run) in runs(row, |cell| if cell.transparent { None } else { Some(cell.attr & 0xF) }) {
+            if run[0].transparent {
+                continue
+            }
+            let xs: Vec<String> = (x..x + run.len()).map(|x| (x as u32 * cw).to_string()).collect();
+            let chars: String = run.iter().map(|cell| escape(glyph(cell))).collect();
+            let fill = hex(style.table[(run[0].attr & 0xF) as usize]);
+            write!(out, "<tspan x=\"{}\" fill=\"{}\">{}</tspan>", xs.join(" "), fill, chars)?;
+        }
+        writeln!(out, "</text>")?;
+    }
+    writeln!(out, "</g>")?;
+    writeln!(out, "</svg>")?;
+    Ok(())
+}
+/// Draws the cells as they would appear in the console, as RGBA pixels `w` times the atlas width
+/// across. Transparent cells are left fully transparent.
+pub fn rasterize(text: &[Cell], w: u32, atlas: &Atlas, table: &[u32; 16]) -> Vec<u8> {
+    let (cw, ch) = (atlas.width, atlas.height);
+    let pw = w * cw;
+    let mut data = vec![0; text.len() * (cw * ch * 4) as usize];
+    for (i, cell) in text.iter().enumerate() {
+        if cell.transparent {
+            continue
+        }
+        let (cx, cy) = (i as u32 % w * cw, i as u32 / w * ch);
+        for y in 0..ch {
+            for x in 0..cw {
+                let color = if atlas.lit(cell.ch, x, y) { cell.attr & 0xF } else { cell.attr >> 4 };
+                let (r, g, b) = rgb(table[color as usize]);
+                let j = (((cy + y) * pw + cx + x) * 4) as usize;
+                data[j..j + 4].copy_from_slice(&[r as u8, g as u8, b as u8, 255]);
+            }
+        }
+    }
+    data
+}
+/// Writes out the cells as a PNG of what they look like when drawn with the glyphs in `atlas`.
+pub fn write_png<W: Write>(out: &mut W, text: &[Cell], w: u32, atlas: &Atlas, table: &[u32; 16]) -> Result<()> {
+    let h = text.len() as u32 / w.max(1);
+    let mut encoder = Encoder::new(out, w * atlas.width, h * atlas.height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rasterize(text, w, atlas, table))?;
+    writer.finish()?;
+    Ok(())
+}
+/// Writes out the cells as ANSI art, with raw code page 437 characters and ANSI.SYS color codes
+/// followed by a SAUCE record. Bright backgrounds rely on iCE colors. ANSI art has no palette of
+/// its own, so each color in `style.table` becomes the closest of the standard sixteen.
+pub fn write_ansi_art<W: Write>(out: &mut W, text: &[Cell], w: u32, style: &Style) -> Result<()> {
+    let mut data = Vec::new();
+    let colors = nearest_standard(&style.table);
+    // Rows as wide as the usual 80 column screen wrap onto the next one by themselves, and a line
+    // break on top of that would leave an empty row in many viewers. Narrower rows need one in
+    // viewers which ignore the width in the SAUCE record.
+    let newline = w < 80;
+    let mut last = None;
+    for row in text.chunks(w as usize) {
+        for cell in row {
+            let (ch, attr) = if cell.transparent { (b' ', 0) } else { (cell.ch, cell.attr) };
+            let (fg, bg) = (colors[(attr & 0xF) as usize], colors[(attr >> 4) as usize]);
+            if last != Some((fg, bg)) {
+                let bold = if fg & 8 != 0 { "1;" } else { "" };
+                let blink = if bg & 8 != 0 { "5;" } else { "" };
+                write!(data, "\x1b[0;{}{}3{};4{}m", bold, blink, swap_red_blue(fg), swap_red_blue(bg))?;
+                last = Some((fg, bg));
+            }
+            // Control characters which viewers would act on instead of drawing are left blank
+            let ch = match ch {
+                0x07..=0x0A | 0x0D | 0x1A | 0x1B => b' ',
+                ch => ch,
+            };
+            data.push(ch);
+        }
+        if newline {
+            data.extend_from_slice(b"\x1b[0m\r\n");
+            last = None;
+        }
+    }
+    if !newline {
+        data.extend_from_slice(b"\x1b[0m");
+    }
+    out.write_all(&data)?;
+    let h = text.len() as u32 / w.max(1);
+    Sauce::for_art(DataType::Ansi, w as u16, h as u16, data.len(), style).write(out)
+}
+// For each color in the table, the standard color closest to it
+fn nearest_standard(table: &[u32; 16]) -> [u8; 16] {
+    let mut colors = [0; 16];
+    for (color, &c) in colors.iter_mut().zip(table) {
+        let (r, g, b) = rgb(c);
+        let dist = |&(sr, sg, sb): &(u8, u8, u8)| {
+            let d = |a: u32, b: u8| (a as i32 - b as i32).pow(2);
+            d(r, sr) + d(g, sg) + d(b, sb)
+        };
+        *color = (0..16).min_by_key(|&i| dist(&COLORS[i])).unwrap() as u8;
+    }
+    colors
+}
+/// Writes out the cells as XBin, which carries the palette from `style.table` and the font from
+/// `atlas` so that the art looks the same as it did here. The font must be eight pixels wide.
+pub fn write_xbin<W: Write>(out: &mut W, text: &[Cell], w: u32, atlas: &Atlas, style: &Style) -> Result<()> {
+    if atlas.width != 8 || atlas.height > 32 {
+        let msg = format!("XBin fonts must be 8 pixels wide and at most 32 tall, not {}x{}", atlas.width, atlas.height);
+        return Err(Error::InvalidDimensions(msg))
+    }
+    let h = text.len() as u32 / w.max(1);
+    let mut data = b"XBIN\x1a".to_vec();
+    data.extend_from_slice(&(w as u16).to_le_bytes());
+    data.extend_from_slice(&(h as u16).to_le_bytes());
+    data.push(atlas.height as u8);
+    // A palette, a font, maybe compression, and bright backgrounds instead of blinking
+    data.push(1 | 2 | (style.compress as u8) << 2 | 8);
+    for &c in &style.table {
+        let (r, g, b) = rgb(c);
+        // Palettes only have six bits per channel
+        data.extend([r, g, b].iter().map(|&x| ((x * 63 + 127) / 255) as u8));
+    }
+    for ch in 0..=255 {
+        for y in 0..atlas.height {
+            data.push((0..8).fold(0, |row, x| row << 1 | atlas.lit(ch, x, y) as u8));
+        }
+    }
+    let cells: Vec<(u8, u8)> = text.iter().map(|cell| {
+        if cell.transparent { (b' ', 0) } else { (cell.ch, cell.attr) }
+    }).collect();
+    for row in cells.chunks(w as usize) {
+        if style.compress {
+            compress_xbin(row, &mut data);
+        } else {
+            for &(ch, attr) in row {
+                data.extend_from_slice(&[ch, attr]);
+            }
+        }
+    }
+    out.write_all(&data)?;
+    Sauce::for_art(DataType::XBin, w as u16, h as u16, data.len(), style).write(out)
+}
+// Compresses one row of XBin cells. Each run starts with a byte whose top two bits say whether
+// the characters, the attributes, or both repeat, and whose low six bits are the length minus one.
+fn compress_xbin(row: &[(u8, u8)], data: &mut Vec<u8>) {
+    // How long each kind of run starting at `i` would be
+    let run = |i: usize, same: &dyn Fn(&(u8, u8)) -> bool| {
+        row[i..].iter().take(64).take_while(|cell| same(cell)).count()
+    };
+    let mut i = 0;
+    while i < row.len() {
+        let (ch, attr) = row[i];
+        let both = run(i, &|c| *c == (ch, attr));
+        let chars = run(i, &|c| c.0 == ch);
+        let attrs = run(i, &|c| c.1 == attr);
+        if both >= 2 {
+            data.extend_from_slice(&[0xC0 | (both - 1) as u8, ch, attr]);
+            i += both;
+        } else if chars >= 3 && chars >= attrs {
+            data.extend_from_slice(&[0x40 | (chars - 1) as u8, ch]);
+            data.extend(row[i..i + chars].iter().map(|c| c.1));
+            i += chars;
+        } else if attrs >= 3 {
+            data.extend_from_slice(&[0x80 | (attrs - 1) as u8, attr]);
+            data.extend(row[i..i + attrs].iter().map(|c| c.0));
+            i += attrs;
+        } else {
+            // Gather cells up until something worth compressing comes along
+            let mut end = i + 1;
+            while end < row.len() && end - i < 64 {
+                let (ch, attr) = row[end];
+                if run(end, &|c| *c == (ch, attr)) >= 2 || run(end, &|c| c.0 == ch) >= 3 || run(end, &|c| c.1 == attr) >= 3 {
+                    break
+                }
+                end += 1;
+            }
+            data.push((end - i - 1) as u8);
+            for &(ch, attr) in &row[i..end] {
+                data.extend_from_slice(&[ch, attr]);
+            }
+            i = end;
+        }
+    }
+}
+/// Writes out the cells as raw character and attribute pairs, followed by a SAUCE record which
+/// gives the width. Only even widths can be recorded there, so rows of an odd width are padded
+/// out with a blank cell. Widths past 510 can't be recorded at all, and have to be given again
+/// when reading the file.
+pub fn write_bin<W: Write>(out: &mut W, text: &[Cell], w: u32, style: &Style) -> Result<()> {
+    let padded = w.div_ceil(2) * 2;
+    let mut data = Vec::with_capacity(text.len() * 2);
+    for row in text.chunks(w.max(1) as usize) {
+        for cell in row {
+            data.extend_from_slice(&if cell.transparent { [b' ', 0] } else { [cell.ch, cell.attr] });
+        }
+        if padded > w {
+            data.extend_from_slice(&[b' ', 0]);
+        }
+    }
+    out.write_all(&data)?;
+    let width = if padded <= 510 { padded } else { 0 };
+    let h = text.len() as u32 / w.max(1);
+    Sauce::for_art(DataType::Binary, width as u16, h as u16, data.len(), style).write(out)
+}
+/// The cells along with the palette they refer to, in a form meant for other programs to read.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Grid {
+    pub width: u32,
+    pub height: u32,
+    /// The sixteen colors as red, green and blue.
+    pub palette: Vec<[u8; 3]>,
+    /// The cells row by row.
+    pub cells: Vec<GridCell>,
+}
+/// A cell of a `Grid`, with the character decoded and the colors as indices into the palette.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GridCell {
+    pub char: char,
+    pub fg: u8,
+    pub bg: u8,
+    /// Whether the cell may be left showing whatever is behind it.
+    pub transparent: bool,
+}
+impl Grid {
+    /// Gathers up the cells, `w` to a row, with the colors from `table`.
+    pub fn new(text: &[Cell], w: u32, table: &[u32; 16]) -> Grid {
+        Grid {
+            width: w,
+            height: text.len() as u32 / w.max(1),
+            palette: table.iter().map(|&c| {
+                let (r, g, b) = rgb(c);
+                [r as u8, g as u8, b as u8]
+            }).collect(),
+            cells: text.iter().map(|cell| GridCell {
+                char: from_u32(CHARS[cell.ch as usize] as u32).unwrap(),
+                fg: cell.attr & 0xF,
+                bg: cell.attr >> 4,
+                transparent: cell.transparent,
+            }).collect(),
+        }
+    }
+}
+/// Writes out the cells as a JSON `Grid` on a single line.
+pub fn write_json<W: Write>(out: &mut W, text: &[Cell], w: u32, table: &[u32; 16]) -> Result<()> {
+    to_writer(&mut *out, &Grid::new(text, w, table))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use import::{read_ansi, read_bin, read_xbin};
+    use Mode;
+
+    fn style() -> Style {
+        Style {
+            table: Mode::Color.color_table(),
+            font_family: String::new(),
+            cell: (8, 16),
+            title: "Title".to_string(),
+            author: String::new(),
+            compress: false,
+            atlas: None,
+        }
+    }
+    // Cells of all sorts of characters and colors, with each color lasting a few cells
+    fn cells(w: u32, h: u32) -> Vec<Cell> {
+        (0..w * h).map(|i| Cell { ch: b'A' + (i % 26) as u8, attr: (i / 3) as u8, transparent: false }).collect()
+    }
+
+    #[test]
+    fn ansi_rows_start_colored() {
+        // Every row is reset at its end, so the next one has to set its colors again even when
+        // they are the same
+        let text = vec![Cell { ch: b'x', attr: 0x17, transparent: false }; 6];
+        let mut data = Vec::new();
+        write_ansi(&mut data, &text, 3, &style().table).unwrap();
+        let data = String::from_utf8(data).unwrap();
+        let rows: Vec<&str> = data.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], rows[1]);
+        assert!(rows[1].starts_with("\x1b[38;2;"));
+    }
+    #[test]
+    fn ansi_art_round_trip() {
+        for &(w, h) in &[(20, 7), (79, 2), (80, 3), (100, 2), (1, 4)] {
+            let text = cells(w, h);
+            let mut data = Vec::new();
+            write_ansi_art(&mut data, &text, w, &style()).unwrap();
+            // Line breaks end the rows of narrow art, which keeps it readable without the width
+            let breaks = data.windows(2).filter(|pair| pair == b"\r\n").count() as u32;
+            assert_eq!(breaks, if w < 80 { h } else { 0 });
+            let art = read_ansi(&data, None);
+            assert_eq!(art.width, w);
+            assert_eq!(art.cells.len(), text.len());
+            for (a, b) in art.cells.iter().zip(&text) {
+                assert_eq!((a.ch, a.attr), (b.ch, b.attr));
+            }
+        }
+    }
+    #[test]
+    fn bin_widths() {
+        // Odd widths gain a column of padding so that they can be recorded
+        let text = cells(7, 3);
+        let mut data = Vec::new();
+        write_bin(&mut data, &text, 7, &style()).unwrap();
+        let art = read_bin(&data, None);
+        assert_eq!((art.width, art.cells.len()), (8, 24));
+        for (row, art_row) in text.chunks(7).zip(art.cells.chunks(8)) {
+            assert!(row.iter().zip(art_row).all(|(a, b)| (a.ch, a.attr) == (b.ch, b.attr)));
+            assert_eq!((art_row[7].ch, art_row[7].attr), (b' ', 0));
+        }
+        // and ones too wide to record have to be given when reading
+        let text = cells(600, 2);
+        data.clear();
+        write_bin(&mut data, &text, 600, &style()).unwrap();
+        assert_eq!(read_bin(&data, None).width, 160);
+        assert_eq!(read_bin(&data, Some(600)).cells.len(), 1200);
+    }
+    #[test]
+    fn xbin_round_trip() {
+        // Runs of each kind longer than a single run can hold, then cells with nothing in common
+        let mut row: Vec<(u8, u8)> = vec![(b'a', 0x1F); 100];
+        row.extend((0..70).map(|i| (b'b', i as u8)));
+        row.extend((0..70).map(|i| (i as u8, 0x2E)));
+        row.extend((0..70).map(|i| ((i * 3) as u8, (i * 5) as u8)));
+        row.extend_from_slice(&[(b'c', 1), (b'c', 1), (b'd', 1), (b'd', 2), (b'e', 3)]);
+        let text: Vec<Cell> = row.iter().chain(&row).map(|&(ch, attr)| Cell { ch, attr, transparent: false }).collect();
+        let w = row.len() as u32;
+        let rows: Vec<u8> = (0..256 * 16).map(|i| i as u8).collect();
+        let atlas = Atlas::from_rows(16, &rows);
+        let mut sizes = Vec::new();
+        for &compress in &[false, true] {
+            let mut data = Vec::new();
+            write_xbin(&mut data, &text, w, &atlas, &Style { compress, ..style() }).unwrap();
+            sizes.push(data.len());
+            let art = read_xbin(&data).unwrap();
+            assert_eq!((art.width, art.cells.len()), (w, text.len()));
+            for (a, b) in art.cells.iter().zip(&text) {
+                assert_eq!((a.ch, a.attr), (b.ch, b.attr));
+            }
+            // The palette loses the low bits of each channel
+            for (&a, &b) in art.palette.unwrap().iter().zip(&style().table) {
+                let (a, b) = (rgb(a), rgb(b));
+                assert!([(a.0, b.0), (a.1, b.1), (a.2, b.2)].iter().all(|&(x, y)| (x as i32 - y as i32).abs() <= 2));
+            }
+            let font = art.font.unwrap();
+            assert!((0..8).all(|x| font.lit(b'A', x, 3) == atlas.lit(b'A', x, 3)));
+        }
+        assert!(sizes[1] < sizes[0]);
+    }
+}
@@ -6,12 +6,17 @@ use std::ops::{Add, AddAssign, Div, Mul, Sub};
 pub struct Pixel(f32, f32, f32);
 impl Pixel {
     pub fn black() -> Pixel { Pixel(0., 0., 0.) }
+    /// A color from linear light components, which may be brighter than white.
+    pub fn new(r: f32, g: f32, b: f32) -> Pixel { Pixel(r, g, b) }
     pub fn from_srgb(r: u8, g: u8, b: u8) -> Pixel {
         let r = SRGB_TO_LINEAR[r as usize];
         let g = SRGB_TO_LINEAR[g as usize];
         let b = SRGB_TO_LINEAR[b as usize];
         Pixel(r, g, b)
     }
+    pub fn map<F: Fn(f32) -> f32>(self, f: F) -> Pixel {
+        Pixel(f(self.0), f(self.1), f(self.2))
+    }
     pub fn luminosity(self) -> f32 {
         0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
     }
@@ -23,6 +28,21 @@ impl Pixel {
         let l = self.luminosity() - o.luminosity();
         l * l
     }
+    /// The color as sRGB samples between zero and one, clipping anything out of range.
+    pub fn to_srgb_f32(self) -> (f32, f32, f32) {
+        let p = self.map(|x| linear_to_srgb(x.clamp(0., 1.)));
+        (p.0, p.1, p.2)
+    }
+    /// The color in CIELAB, relative to a D65 white.
+    pub fn to_lab(self) -> (f32, f32, f32) {
+        let Pixel(r, g, b) = self;
+        let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+        let f = |t: f32| if t > 216. / 24389. { t.cbrt() } else { (24389. / 27. * t + 16.) / 116. };
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        (116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz))
+    }
 }
 impl Add<Pixel> for Pixel {
     type Output = Pixel;
@@ -55,7 +75,24 @@ impl Sub<Pixel> for Pixel {
         Pixel(self.0 - o.0, self.1 - o.1, self.2 - o.2)
     }
 }
-const SRGB_TO_LINEAR: &'static [f32; 256] = &[
+/// The sRGB transfer function, which `SRGB_TO_LINEAR` is a table of.
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+/// The inverse of `srgb_to_linear`.
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+#[allow(clippy::excessive_precision)]
+const SRGB_TO_LINEAR: &[f32; 256] = &[
     0.0000000000e0, 3.0352698355e-4, 6.0705396710e-4, 9.1058095065e-4, 1.2141079342e-3,
     1.5176349177e-3, 1.8211619013e-3, 2.1246888848e-3, 2.4282158684e-3, 2.7317428519e-3,
     3.0352698355e-3, 3.3465357639e-3, 3.6765073240e-3, 4.0247170185e-3, 4.3914420374e-3,
@@ -0,0 +1,116 @@
+// Copyright © 2016, Peter Atashian
+
+use output::{rasterize};
+use pixel::{linear_to_srgb};
+use png::{BitDepth, ColorType, Encoder};
+use serde::{Deserialize, Serialize};
+use std::io::{Write};
+use {Atlas, Cell, Image, Result};
+
+// How many pixels on a side SSIM looks at at once
+const WINDOW: u32 = 8;
+
+/// How faithful a conversion is to the image it was made from, as found by `measure`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Quality {
+    /// Mean squared error of the sRGB samples, which range from zero to one.
+    pub mse: f32,
+    /// Peak signal to noise ratio in decibels, which is infinite for a perfect match.
+    pub psnr: f32,
+    /// Mean structural similarity of the luma, where one is a perfect match.
+    pub ssim: f32,
+    /// Mean CIE76 color difference, where around 2.3 is just noticeable.
+    pub delta_e: f32,
+}
+/// Draws the cells, `w` to a row, with the glyphs in `atlas` and the colors from `table`, and
+/// compares that with `source` once both have been shrunk to the same size. `area` is the part of
+/// the drawing, from its top left corner, which the source was converted into, leaving out any
+/// padding which filled out the last row and column of cells.
+pub fn measure(source: &Image, area: (u32, u32), text: &[Cell], w: u32, atlas: &Atlas, table: &[u32; 16]) -> Quality {
+    let (fw, fh) = (w * atlas.width, text.len() as u32 / w.max(1) * atlas.height);
+    let (rw, rh) = (area.0.clamp(1, fw.max(1)), area.1.clamp(1, fh.max(1)));
+    let rgba: Vec<(u8, u8, u8, u8)> = rasterize(text, w, atlas, table).chunks((fw as usize * 4).max(4)).take(rh as usize).flat_map(|row| {
+        row.chunks(4).take(rw as usize).map(|p| (p[0], p[1], p[2], p[3]))
+    }).collect();
+    // Transparent cells show the background the source was composited over
+    let rendered = Image::from_srgba(&rgba, rw, rh, source.background);
+    // Neither is ever enlarged, so that one can't be blurred into looking like the other
+    let (cw, ch) = (source.width.min(rw).max(1), source.height.min(rh).max(1));
+    let a = source.resize(cw, ch);
+    let b = rendered.resize(cw, ch);
+    let count = a.pixels.len() as f32;
+    let (sa, sb): (Vec<_>, Vec<_>) = a.pixels.iter().zip(&b.pixels).map(|(a, b)| {
+        (a.to_srgb_f32(), b.to_srgb_f32())
+    }).unzip();
+    let mse = sa.iter().zip(&sb).map(|(a, b)| {
+        (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)
+    }).sum::<f32>() / (count * 3.);
+    let luma = |p: &(f32, f32, f32)| 0.2126 * p.0 + 0.7152 * p.1 + 0.0722 * p.2;
+    let ya: Vec<f32> = sa.iter().map(luma).collect();
+    let yb: Vec<f32> = sb.iter().map(luma).collect();
+    let delta_e = a.pixels.iter().zip(&b.pixels).map(|(a, b)| {
+        let (a, b) = (a.to_lab(), b.to_lab());
+        ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+    }).sum::<f32>() / count;
+    Quality {
+        mse,
+        psnr: -10. * mse.log10(),
+        ssim: ssim(&ya, &yb, cw, ch),
+        delta_e,
+    }
+}
+// The mean SSIM over windows overlapping by half, or over the whole image if it is too small
+fn ssim(a: &[f32], b: &[f32], w: u32, h: u32) -> f32 {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+    let (sw, sh) = (WINDOW.min(w), WINDOW.min(h));
+    let n = (sw * sh) as f32;
+    let mut total = 0.;
+    let mut windows = 0;
+    for y in (0..=h - sh).step_by((sh as usize / 2).max(1)) {
+        for x in (0..=w - sw).step_by((sw as usize / 2).max(1)) {
+            let index = |i: u32| ((y + i / sw) * w + x + i % sw) as usize;
+            let (ma, mb) = (0..sw * sh).fold((0., 0.), |(ma, mb), i| (ma + a[index(i)], mb + b[index(i)]));
+            let (ma, mb) = (ma / n, mb / n);
+            let (va, vb, cov) = (0..sw * sh).fold((0., 0., 0.), |(va, vb, cov), i| {
+                let (da, db) = (a[index(i)] - ma, b[index(i)] - mb);
+                (va + da * da, vb + db * db, cov + da * db)
+            });
+            let (va, vb, cov) = (va / n, vb / n, cov / n);
+            total += (2. * ma * mb + C1) * (2. * cov + C2) / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f32
+}
+/// Writes out a PNG with a block of `cell` pixels for each residual, `w` to a row, going from
+/// black for none through red and yellow to white for a whole unit of linear light or more. The
+/// scale is fixed so that heatmaps made with different settings can be compared.
+pub fn write_heatmap<W: Write>(out: &mut W, error: &[f32], w: u32, cell: (u32, u32)) -> Result<()> {
+    let (cw, ch) = cell;
+    let pw = w * cw;
+    let h = error.len() as u32 / w.max(1);
+    let mut data = vec![0; (pw * h * ch * 3) as usize];
+    for (i, &e) in error.iter().enumerate() {
+        let color = heat(linear_to_srgb(e.clamp(0., 1.)));
+        let (cx, cy) = (i as u32 % w * cw, i as u32 / w * ch);
+        for y in cy..cy + ch {
+            for x in cx..cx + cw {
+                let j = ((y * pw + x) * 3) as usize;
+                data[j..j + 3].copy_from_slice(&color);
+            }
+        }
+    }
+    let mut encoder = Encoder::new(out, pw, h * ch);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+// Black, red, yellow and white a third of the way apart
+fn heat(t: f32) -> [u8; 3] {
+    let channel = |start: f32| ((t * 3. - start).clamp(0., 1.) * 255.).round() as u8;
+    [channel(0.), channel(1.), channel(2.)]
+}
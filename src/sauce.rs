@@ -0,0 +1,234 @@
+// Copyright © 2016, Peter Atashian
+
+use output::{Style};
+use serde::{Deserialize, Serialize};
+use std::char::{from_u32};
+use std::io::{Write};
+use std::ops::{Range};
+use std::time::{SystemTime, UNIX_EPOCH};
+use {CHARS, Result};
+
+/// Kinds of files a SAUCE record can describe, as its data type and file type.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    Ansi,
+    /// Raw character and attribute pairs, with the width stored in the file type. Only even widths
+    /// up to 510 fit there, and odd ones are rounded up.
+    Binary,
+    XBin,
+}
+/// The metadata record that ANSI art tools expect to find at the end of a file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sauce {
+    pub title: String,
+    pub author: String,
+    pub group: String,
+    /// The date the file was made, as `YYYYMMDD`.
+    pub date: String,
+    /// How many bytes of data come before the record.
+    pub file_size: u32,
+    pub data_type: DataType,
+    /// Width and height in characters.
+    pub width: u16,
+    pub height: u16,
+    /// Whether the high bit of the attribute selects bright backgrounds rather than blinking.
+    pub ice_colors: bool,
+    /// The name of the font the art was drawn for, such as `IBM VGA`.
+    pub font: String,
+}
+impl Sauce {
+    /// A record dated today with nothing filled in yet.
+    pub fn new(data_type: DataType, width: u16, height: u16) -> Sauce {
+        Sauce {
+            title: String::new(),
+            author: String::new(),
+            group: String::new(),
+            date: today(),
+            file_size: 0,
+            data_type,
+            width,
+            height,
+            ice_colors: false,
+            font: String::new(),
+        }
+    }
+    /// A record for `file_size` bytes of art written out in `style`, which always uses bright
+    /// backgrounds rather than blinking.
+    pub fn for_art(data_type: DataType, width: u16, height: u16, file_size: usize, style: &Style) -> Sauce {
+        let mut sauce = Sauce::new(data_type, width, height);
+        sauce.title = style.title.clone();
+        sauce.author = style.author.clone();
+        sauce.file_size = file_size as u32;
+        sauce.ice_colors = true;
+        // XBin carries its own font
+        if data_type != DataType::XBin {
+            sauce.font = font_name(style.cell).to_string();
+        }
+        sauce
+    }
+    /// Writes the end of file marker followed by the 128 byte record.
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        let mut rec = Vec::with_capacity(129);
+        rec.push(0x1A);
+        rec.extend_from_slice(b"SAUCE00");
+        rec.extend(field(&self.title, 35, b' '));
+        rec.extend(field(&self.author, 20, b' '));
+        rec.extend(field(&self.group, 20, b' '));
+        rec.extend(field(&self.date, 8, b' '));
+        rec.extend_from_slice(&self.file_size.to_le_bytes());
+        let (data_type, file_type, width) = match self.data_type {
+            DataType::Ansi => (1, 1, self.width),
+            // Binary text keeps half the width in the file type, and has no room for it otherwise
+            DataType::Binary => (5, if self.width <= 510 { self.width.div_ceil(2) as u8 } else { 0 }, 0),
+            DataType::XBin => (6, 0, 0),
+        };
+        rec.push(data_type);
+        rec.push(file_type);
+        let height = if self.data_type == DataType::Ansi { self.height } else { 0 };
+        for info in &[width, height, 0, 0] {
+            rec.extend_from_slice(&info.to_le_bytes());
+        }
+        // No comments
+        rec.push(0);
+        // Eight pixel wide letters, as in the atlases
+        rec.push(self.ice_colors as u8 | 1 << 1);
+        rec.extend(field(&self.font, 22, 0));
+        out.write_all(&rec)?;
+        Ok(())
+    }
+    /// Looks for a record at the end of `data`, returning it along with the data that comes before
+    /// it, without the end of file marker or any comments.
+    pub fn read(data: &[u8]) -> (Option<Sauce>, &[u8]) {
+        if data.len() < 128 || &data[data.len() - 128..data.len() - 121] != b"SAUCE00" {
+            return (None, data)
+        }
+        let rec = &data[data.len() - 128..];
+        let text = |range: Range<usize>| -> String {
+            let s: String = rec[range].iter().map(|&b| from_u32(CHARS[b as usize] as u32).unwrap()).collect();
+            // Padding with NUL comes out as spaces too
+            s.trim_end_matches(' ').to_string()
+        };
+        let u16_at = |i: usize| u16::from_le_bytes([rec[i], rec[i + 1]]);
+        let data_type = match rec[94] {
+            5 => DataType::Binary,
+            6 => DataType::XBin,
+            _ => DataType::Ansi,
+        };
+        let (width, height) = match data_type {
+            DataType::Binary => (rec[95] as u16 * 2, 0),
+            DataType::XBin => (0, 0),
+            DataType::Ansi => (u16_at(96), u16_at(98)),
+        };
+        let sauce = Sauce {
+            title: text(7..42),
+            author: text(42..62),
+            group: text(62..82),
+            date: text(82..90),
+            file_size: u32::from_le_bytes([rec[90], rec[91], rec[92], rec[93]]),
+            data_type,
+            width,
+            height,
+            ice_colors: rec[105] & 1 != 0,
+            font: text(106..128),
+        };
+        // Comments come just before the record, and the end of file marker before them
+        let mut end = data.len() - 128;
+        let comments = 5 + 64 * rec[104] as usize;
+        if rec[104] > 0 && end >= comments && &data[end - comments..end - comments + 5] == b"COMNT" {
+            end -= comments;
+        }
+        if end > 0 && data[end - 1] == 0x1A {
+            end -= 1;
+        }
+        (Some(sauce), &data[..end])
+    }
+}
+/// Encodes a string as code page 437, padded or cut to exactly `len` bytes. Characters with no
+/// equivalent become question marks.
+fn field(s: &str, len: usize, pad: u8) -> Vec<u8> {
+    let mut bytes: Vec<u8> = s.chars().map(|c| {
+        CHARS.iter().skip(32).position(|&x| x as u32 == c as u32).map(|i| i as u8 + 32).unwrap_or(b'?')
+    }).take(len).collect();
+    bytes.resize(len, pad);
+    bytes
+}
+/// The name SAUCE uses for the standard font with cells of this size, if there is one.
+pub fn font_name((w, h): (u32, u32)) -> &'static str {
+    match (w, h) {
+        (8, 16) => "IBM VGA",
+        (8, 14) => "IBM EGA",
+        (8, 8) => "IBM VGA50",
+        _ => "",
+    }
+}
+/// Turns one of the eight ANSI colors, which go red, green, blue, into the matching console color,
+/// which go blue, green, red, or the other way around.
+pub fn swap_red_blue(c: u8) -> u8 {
+    (c & 1) << 2 | (c & 2) | (c & 4) >> 2
+}
+// Today's date in UTC as YYYYMMDD, converted from days since the epoch
+fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (m <= 2) as i64;
+    format!("{:04}{:02}{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(sauce: &Sauce) -> Sauce {
+        let mut data = b"art".to_vec();
+        sauce.write(&mut data).unwrap();
+        assert_eq!(data.len(), 3 + 129);
+        let (read, rest) = Sauce::read(&data);
+        assert_eq!(rest, b"art");
+        read.unwrap()
+    }
+
+    #[test]
+    fn fields() {
+        let mut sauce = Sauce::new(DataType::Ansi, 80, 25);
+        sauce.title = "Caf\u{e9} \u{2591}".to_string();
+        sauce.author = "An author whose name is too long".to_string();
+        sauce.group = "\u{1F600}".to_string();
+        sauce.file_size = 3;
+        sauce.ice_colors = true;
+        sauce.font = "IBM VGA".to_string();
+        let read = round_trip(&sauce);
+        assert_eq!(read.title, "Caf\u{e9} \u{2591}");
+        assert_eq!(read.author, "An author whose name");
+        assert_eq!(read.group, "?");
+        assert_eq!(read.date, sauce.date);
+        assert_eq!(read.file_size, 3);
+        assert_eq!(read.data_type, DataType::Ansi);
+        assert_eq!((read.width, read.height), (80, 25));
+        assert!(read.ice_colors);
+        assert_eq!(read.font, "IBM VGA");
+    }
+    #[test]
+    fn widths() {
+        for &(w, expected) in &[(160, 160), (7, 8), (510, 510), (511, 0), (1000, 0)] {
+            let read = round_trip(&Sauce::new(DataType::Binary, w, 10));
+            assert_eq!(read.data_type, DataType::Binary);
+            assert_eq!(read.width, expected);
+        }
+        let read = round_trip(&Sauce::new(DataType::XBin, 80, 25));
+        assert_eq!((read.data_type, read.width, read.height), (DataType::XBin, 0, 0));
+    }
+    #[test]
+    fn no_record() {
+        let (sauce, data) = Sauce::read(b"SAUCE00 but too short");
+        assert!(sauce.is_none());
+        assert_eq!(data, b"SAUCE00 but too short");
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright © 2016, Peter Atashian
+
+use pixel::{Pixel};
+use std::ops::{Add, AddAssign, Mul, Sub};
+use Weights;
+
+/// A foreground and a background, each as an index into the color table along with that color
+/// in some space.
+pub type Pair<C> = ((u8, C), (u8, C));
+/// What `make_text` matches pixels to cells in. Each `Mode` is one of these, and other modes can
+/// be made by implementing it and passing it to `convert_in`.
+pub trait ColorSpace {
+    /// A color in this space, which the error of each cell is also diffused onto its neighbours
+    /// in.
+    type Color: Copy + Add<Output = Self::Color> + AddAssign + Sub<Output = Self::Color> + Mul<f32, Output = Self::Color>;
+    /// Brings a pixel of the image into this space.
+    fn color(&self, pixel: Pixel) -> Self::Color;
+    /// Every foreground and background a cell may have. Ties go to whichever comes first.
+    fn pairs(&self) -> Vec<Pair<Self::Color>>;
+    /// How badly a cell with this foreground and background, which its character blends into
+    /// `mixed`, stands in for `pixel`.
+    fn distance(&self, pixel: Self::Color, fg: Self::Color, bg: Self::Color, mixed: Self::Color) -> f32;
+    /// How big a difference between two colors is, in linear light.
+    fn magnitude(&self, diff: Self::Color) -> f32;
+}
+// An entry of a color table, which has red in the low byte
+fn entry(c: u32) -> Pixel {
+    Pixel::from_srgb(c as u8, (c >> 8) as u8, (c >> 16) as u8)
+}
+// Every foreground with every background, with the backgrounds changing fastest
+fn all_pairs<C: Copy>(colors: &[C]) -> Vec<Pair<C>> {
+    let mut pairs = Vec::with_capacity(colors.len() * colors.len());
+    for (fg, &c1) in colors.iter().enumerate() {
+        for (bg, &c2) in colors.iter().enumerate() {
+            pairs.push(((fg as u8, c1), (bg as u8, c2)));
+        }
+    }
+    pairs
+}
+/// Matches the full color of pixels with any pair from a palette, as `Mode::Color` does.
+pub struct Rgb {
+    colors: Vec<Pixel>,
+    weights: Weights,
+}
+impl Rgb {
+    pub fn new(palette: &[u32; 16], weights: Weights) -> Rgb {
+        Rgb { colors: palette.iter().map(|&c| entry(c)).collect(), weights }
+    }
+}
+impl ColorSpace for Rgb {
+    type Color = Pixel;
+    fn color(&self, pixel: Pixel) -> Pixel {
+        pixel
+    }
+    fn pairs(&self) -> Vec<Pair<Pixel>> {
+        all_pairs(&self.colors)
+    }
+    fn distance(&self, pixel: Pixel, fg: Pixel, bg: Pixel, mixed: Pixel) -> f32 {
+        let d1 = pixel.lum_diff(fg);
+        let d2 = pixel.lum_diff(bg);
+        let dd = pixel.diff_sq(mixed);
+        (d1 + d2) * 0.1 * self.weights.contrast + dd * self.weights.color
+    }
+    fn magnitude(&self, diff: Pixel) -> f32 {
+        diff.diff_sq(Pixel::black()).sqrt()
+    }
+}
+/// Matches only the luminosity of pixels and of the palette, as `Mode::Grayscale` does.
+pub struct Luminance {
+    colors: Vec<f32>,
+    weights: Weights,
+}
+impl Luminance {
+    pub fn new(palette: &[u32; 16], weights: Weights) -> Luminance {
+        Luminance { colors: palette.iter().map(|&c| entry(c).luminosity()).collect(), weights }
+    }
+}
+impl ColorSpace for Luminance {
+    type Color = f32;
+    fn color(&self, pixel: Pixel) -> f32 {
+        pixel.luminosity()
+    }
+    fn pairs(&self) -> Vec<Pair<f32>> {
+        all_pairs(&self.colors)
+    }
+    fn distance(&self, pixel: f32, fg: f32, bg: f32, mixed: f32) -> f32 {
+        let d1 = (pixel - fg).abs();
+        let d2 = (pixel - bg).abs();
+        let dd = (pixel - mixed).abs();
+        (d1 + d2) * 0.05 * self.weights.contrast + dd * self.weights.color
+    }
+    fn magnitude(&self, diff: f32) -> f32 {
+        diff.abs()
+    }
+}
+/// Picks only characters, to be drawn in white on black as `Mode::Monochrome` does.
+pub struct Monochrome;
+impl ColorSpace for Monochrome {
+    type Color = f32;
+    fn color(&self, pixel: Pixel) -> f32 {
+        pixel.luminosity()
+    }
+    fn pairs(&self) -> Vec<Pair<f32>> {
+        // White and black in its color table
+        vec![((0, 1.), (2, 0.))]
+    }
+    fn distance(&self, pixel: f32, _: f32, _: f32, mixed: f32) -> f32 {
+        (pixel - mixed).abs()
+    }
+    fn magnitude(&self, diff: f32) -> f32 {
+        diff.abs()
+    }
+}
+/// A color with any number of channels, for spaces which need something other than luminosity
+/// or RGB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Channels<const N: usize>(pub [f32; N]);
+impl<const N: usize> Channels<N> {
+    /// The Euclidean length, which makes for a `magnitude`.
+    pub fn length(self) -> f32 {
+        self.0.iter().map(|x| x * x).sum::<f32>().sqrt()
+    }
+}
+impl<const N: usize> Add<Channels<N>> for Channels<N> {
+    type Output = Channels<N>;
+    fn add(mut self, o: Channels<N>) -> Channels<N> {
+        self += o;
+        self
+    }
+}
+impl<const N: usize> AddAssign<Channels<N>> for Channels<N> {
+    fn add_assign(&mut self, o: Channels<N>) {
+        for (a, b) in self.0.iter_mut().zip(&o.0) {
+            *a += b;
+        }
+    }
+}
+impl<const N: usize> Mul<f32> for Channels<N> {
+    type Output = Channels<N>;
+    fn mul(self, o: f32) -> Channels<N> {
+        Channels(self.0.map(|x| x * o))
+    }
+}
+impl<const N: usize> Sub<Channels<N>> for Channels<N> {
+    type Output = Channels<N>;
+    fn sub(mut self, o: Channels<N>) -> Channels<N> {
+        for (a, b) in self.0.iter_mut().zip(&o.0) {
+            *a -= b;
+        }
+        self
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright © 2016, Peter Atashian
+
+use ascii::{Animation, Cell, Char, Frame, Image, Result, convert};
+use ascii::output::{write_ansi};
+use libc::{STDOUT_FILENO, TIOCGWINSZ, ioctl, winsize};
+use std::io::{Write, stdout};
+use std::mem::{zeroed};
+use std::thread::{sleep};
+use std::time::{Duration, Instant};
+use {DEFAULT_FONT, Options, fit_image};
+
+/// Size in columns and rows of the terminal that stdout is attached to, if any.
+pub fn window_size() -> Option<(u32, u32)> {
+    let mut size: winsize = unsafe { zeroed() };
+    if unsafe { ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None
+    }
+    Some((size.ws_col as u32, size.ws_row as u32))
+}
+// Everything needed to turn images into escape codes for this terminal
+struct Renderer {
+    font: (u32, u32),
+    window: Option<(u32, u32)>,
+    chars: Vec<(Vec<Char>, f32)>,
+    table: [u32; 16],
+}
+impl Renderer {
+    fn new(opts: &Options) -> Result<Renderer> {
+        // There is no way to ask a terminal for its font, so assume VGA sized cells
+        let font = opts.font.unwrap_or(DEFAULT_FONT);
+        Ok(Renderer {
+            font,
+            // Leave the last row free for the prompt
+            window: window_size().map(|(w, h)| (w, h.saturating_sub(1).max(1))),
+            chars: opts.chars(font)?,
+            table: opts.table(),
+        })
+    }
+    // Returns the escape codes for the image along with how many rows they take up
+    fn render(&self, img: Image, opts: &Options) -> Result<(Vec<u8>, u32)> {
+        let img = fit_image(img, self.font, opts, self.window);
+        let (w, h) = (img.width, img.height);
+        let text = convert(&opts.settings(), img, &self.chars)?;
+        let mut buf = Vec::new();
+        write_ansi(&mut buf, &text, w, &self.table)?;
+        Ok((buf, h))
+    }
+}
+pub fn display(anim: Animation, opts: &Options) -> Result<()> {
+    let renderer = Renderer::new(opts)?;
+    // Render every frame up front so playback isn't held up by the conversion
+    let frames = anim.frames.into_iter().map(|frame| {
+        let (buf, h) = renderer.render(frame.image, opts)?;
+        Ok((buf, h, frame.delay))
+    }).collect::<Result<Vec<_>>>()?;
+    let out = stdout();
+    let mut out = out.lock();
+    // Playing an animation into a file or pipe makes no sense, so only the first frame goes there
+    if frames.len() == 1 || renderer.window.is_none() {
+        out.write_all(&frames[0].0)?;
+        return Ok(())
+    }
+    let mut shown = None;
+    let mut plays = 0;
+    while anim.loops == 0 || plays < anim.loops {
+        for &(ref buf, h, delay) in &frames {
+            // Move back up over the previous frame to draw over it
+            if let Some(h) = shown {
+                write!(out, "\x1b[{}A", h)?;
+            }
+            out.write_all(buf)?;
+            out.flush()?;
+            shown = Some(h);
+            sleep(delay);
+        }
+        plays += 1;
+    }
+    Ok(())
+}
+/// Plays frames as they arrive, skipping any which would already be late by the time they were
+/// converted.
+pub fn stream<I: Iterator<Item = Frame>>(frames: I, opts: &Options) -> Result<()> {
+    let renderer = Renderer::new(opts)?;
+    let out = stdout();
+    let mut out = out.lock();
+    let start = Instant::now();
+    let mut due = Duration::from_secs(0);
+    let mut shown = None;
+    for frame in frames {
+        due += frame.delay;
+        if start.elapsed() > due {
+            continue
+        }
+        let (buf, h) = renderer.render(frame.image, opts)?;
+        // Only redraw in place on a terminal, so that files get every frame in turn
+        if let (Some(h), Some(_)) = (shown, renderer.window) {
+            write!(out, "\x1b[{}A", h)?;
+        }
+        out.write_all(&buf)?;
+        out.flush()?;
+        shown = Some(h);
+        if let Some(wait) = (start + due).checked_duration_since(Instant::now()) {
+            sleep(wait);
+        }
+    }
+    Ok(())
+}
+/// Prints cells which have already been converted, in the colors from `table`.
+pub fn show_text(text: &[Cell], w: u32, table: &[u32; 16]) -> Result<()> {
+    let out = stdout();
+    write_ansi(&mut out.lock(), text, w, table)
+}
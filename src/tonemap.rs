@@ -0,0 +1,43 @@
+// Copyright © 2016, Peter Atashian
+
+use pixel::{Pixel};
+use serde::{Deserialize, Serialize};
+use std::str::{FromStr};
+
+/// How colors brighter than white are brought back into range before conversion.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMap {
+    /// Anything brighter than white is clipped.
+    Clamp,
+    /// Reinhard's `x / (1 + x)`, which rolls off highlights but never quite reaches white.
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+impl FromStr for ToneMap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err("expected clamp, reinhard or aces".to_string()),
+        }
+    }
+}
+impl ToneMap {
+    /// Scales the color by `exposure` stops and then maps it into `[0, 1]`. Only `Clamp` leaves
+    /// colors which were already in range as they are, and then only at zero exposure.
+    pub fn apply(self, p: Pixel, exposure: f32) -> Pixel {
+        let scale = exposure.exp2();
+        p.map(|x| {
+            let x = (x * scale).max(0.);
+            match self {
+                ToneMap::Clamp => x.min(1.),
+                ToneMap::Reinhard => x / (1. + x),
+                ToneMap::Aces => ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.),
+            }
+        })
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright © 2016, Peter Atashian
+
+use animation::{Frame};
+use error::{Error, Result};
+use std::io::{self, BufRead, ErrorKind};
+use std::time::{Duration};
+use Image;
+
+// The most pixels a frame can have, twice that of 8K video, so that a header can't make every
+// frame take up more memory than there is
+const MAX_PIXELS: u64 = 1 << 26;
+// How the chroma planes of a Y4M stream are subsampled
+#[derive(Clone, Copy)]
+enum Chroma {
+    C420,
+    C422,
+    C444,
+    Mono,
+}
+#[derive(Clone, Copy)]
+enum Format {
+    Rgb24,
+    Y4m(Chroma),
+}
+/// Reads uncompressed video frames one at a time from a stream, such as the output of `ffmpeg`.
+pub struct VideoReader<R> {
+    input: R,
+    format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// How long each frame stays on screen.
+    pub delay: Duration,
+}
+impl<R: BufRead> VideoReader<R> {
+    /// Reads the header of a YUV4MPEG2 stream. Only 8-bit streams are supported.
+    pub fn y4m(mut input: R) -> Result<VideoReader<R>> {
+        let mut header = String::new();
+        input.read_line(&mut header)?;
+        let mut params = header.split_whitespace();
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(Error::Decode("not a YUV4MPEG2 stream".to_string()))
+        }
+        let (mut width, mut height, mut fps, mut chroma) = (0, 0, 25., Chroma::C420);
+        for param in params {
+            // Tags are a single letter, but the stream may hold anything in their place
+            let mut chars = param.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = value.parse().map_err(|_| Error::InvalidDimensions(format!("bad width {}", value)))?,
+                Some('H') => height = value.parse().map_err(|_| Error::InvalidDimensions(format!("bad height {}", value)))?,
+                Some('F') => {
+                    let mut ratio = value.split(':').map(|x| x.parse::<f32>().unwrap_or(0.));
+                    let (n, d) = (ratio.next().unwrap_or(0.), ratio.next().unwrap_or(1.));
+                    if n > 0. && d > 0. {
+                        fps = n / d;
+                    }
+                },
+                Some('C') => chroma = match value {
+                    "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+                    "422" => Chroma::C422,
+                    "444" => Chroma::C444,
+                    "mono" => Chroma::Mono,
+                    _ => return Err(Error::Decode(format!("unsupported colorspace {}", value))),
+                },
+                // Interlacing, aspect ratio and comments make no difference here
+                _ => (),
+            }
+        }
+        let mut video = VideoReader::rgb24(input, width, height, fps)?;
+        video.format = Format::Y4m(chroma);
+        Ok(video)
+    }
+    /// Reads a stream of packed 8-bit RGB frames, which carries no header of its own.
+    pub fn rgb24(input: R, width: u32, height: u32, fps: f32) -> Result<VideoReader<R>> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions(format!("{}x{} video", width, height)))
+        }
+        if width as u64 * height as u64 > MAX_PIXELS {
+            return Err(Error::InvalidDimensions(format!("{}x{} video has more than {} pixels a frame", width, height, MAX_PIXELS)))
+        }
+        Ok(VideoReader {
+            input,
+            format: Format::Rgb24,
+            width,
+            height,
+            delay: Duration::from_secs(0),
+        }.with_fps(fps))
+    }
+    /// Overrides the frame rate given by the stream.
+    pub fn with_fps(mut self, fps: f32) -> VideoReader<R> {
+        self.delay = Duration::from_nanos((1e9 / fps) as u64);
+        self
+    }
+    // Fills `buf` completely, unless the stream has ended cleanly before the first byte.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.input.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(truncated()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+    fn read_frame(&mut self) -> Result<Option<Image>> {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let chroma = match self.format {
+            Format::Rgb24 => {
+                let mut buf = vec![0; w * h * 3];
+                if !self.fill(&mut buf)? {
+                    return Ok(None)
+                }
+                let pixels: Vec<_> = buf.chunks(3).map(|p| (p[0], p[1], p[2])).collect();
+                return Ok(Some(Image::from_srgb(&pixels, self.width, self.height)))
+            },
+            Format::Y4m(chroma) => chroma,
+        };
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None)
+        }
+        if !line.starts_with("FRAME") {
+            return Err(Error::Decode("missing frame header".to_string()))
+        }
+        let (cw, ch) = match chroma {
+            Chroma::C420 => (w.div_ceil(2), h.div_ceil(2)),
+            Chroma::C422 => (w.div_ceil(2), h),
+            Chroma::C444 => (w, h),
+            Chroma::Mono => (0, 0),
+        };
+        let mut buf = vec![0; w * h + cw * ch * 2];
+        if !self.fill(&mut buf)? {
+            return Err(truncated())
+        }
+        let (luma, rest) = buf.split_at(w * h);
+        let (cb, cr) = rest.split_at(cw * ch);
+        let pixels: Vec<_> = (0..w * h).map(|i| {
+            let (x, y) = (i % w, i / w);
+            if cw == 0 {
+                return ycbcr(luma[i], 128, 128)
+            }
+            let j = y * ch / h * cw + x * cw / w;
+            ycbcr(luma[i], cb[j], cr[j])
+        }).collect();
+        Ok(Some(Image::from_srgb(&pixels, self.width, self.height)))
+    }
+}
+impl<R: BufRead> Iterator for VideoReader<R> {
+    type Item = Result<Frame>;
+    fn next(&mut self) -> Option<Result<Frame>> {
+        let delay = self.delay;
+        match self.read_frame() {
+            Ok(Some(image)) => Some(Ok(Frame { image, delay })),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+// A stream which ends partway through a frame
+fn truncated() -> Error {
+    Error::Io(io::Error::new(ErrorKind::UnexpectedEof, "truncated frame"))
+}
+// Converts limited range BT.601 YCbCr, which is what Y4M streams use unless told otherwise.
+fn ycbcr(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
+    let y = (y as f32 - 16.) * 255. / 219.;
+    let cb = (cb as f32 - 128.) * 255. / 224.;
+    let cr = (cr as f32 - 128.) * 255. / 224.;
+    let c = |x: f32| x.round().clamp(0., 255.) as u8;
+    (c(y + 1.402 * cr), c(y - 0.344136 * cb - 0.714136 * cr), c(y + 1.772 * cb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A stream header followed by one frame of the given planes
+    fn stream(header: &str, planes: &[&[u8]]) -> Vec<u8> {
+        let mut data = format!("{}\nFRAME\n", header).into_bytes();
+        for plane in planes {
+            data.extend_from_slice(plane);
+        }
+        data
+    }
+    fn srgb(image: &Image, i: usize) -> (u8, u8, u8) {
+        let (r, g, b) = image.pixels[i].to_srgb_f32();
+        let c = |x: f32| (x * 255.).round() as u8;
+        (c(r), c(g), c(b))
+    }
+
+    #[test]
+    fn header() {
+        let data = stream("YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C420jpeg XCOMMENT", &[&[16; 8], &[128; 2], &[128; 2]]);
+        let video = VideoReader::y4m(&data[..]).unwrap();
+        assert_eq!((video.width, video.height), (4, 2));
+        assert_eq!(video.delay, Duration::from_nanos((1e9 / (30000. / 1001.)) as u64));
+    }
+    #[test]
+    fn bad_headers() {
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 \xc3\xa9\n"[..]).is_err());
+        // Tags that aren't understood are skipped, whatever they start with
+        assert!(VideoReader::y4m("YUV4MPEG2 W4 H2 \u{e9}\n".as_bytes()).is_ok());
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 W4 H0\n"[..]).is_err());
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 W100000 H100000\n"[..]).is_err());
+        assert!(VideoReader::rgb24(&b""[..], u32::MAX, u32::MAX, 25.).is_err());
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 W4 H2 C411\n"[..]).is_err());
+        assert!(VideoReader::y4m(&b"RIFF"[..]).is_err());
+    }
+    #[test]
+    fn frame_420() {
+        // Black and white columns, with the right half given a red chroma sample
+        let luma = [16, 235, 16, 235, 16, 235, 16, 235];
+        let data = stream("YUV4MPEG2 W4 H2 C420", &[&luma, &[128, 128], &[128, 240]]);
+        let mut video = VideoReader::y4m(&data[..]).unwrap();
+        let frame = video.next().unwrap().unwrap();
+        assert_eq!((frame.image.width, frame.image.height), (4, 2));
+        assert_eq!(srgb(&frame.image, 0), (0, 0, 0));
+        assert_eq!(srgb(&frame.image, 5), (255, 255, 255));
+        let (r, g, b) = srgb(&frame.image, 2);
+        assert!(r > 128 && g == 0 && b == 0);
+        assert!(video.next().is_none());
+    }
+    #[test]
+    fn truncated_frame() {
+        let data = stream("YUV4MPEG2 W4 H2 C420", &[&[16; 8], &[128; 2]]);
+        let mut video = VideoReader::y4m(&data[..]).unwrap();
+        assert!(video.next().unwrap().is_err());
+    }
+}